@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use chip8::{Chip8, Display, DrawMode};
+
+/// A loop mixing arithmetic, memory access, skips and a draw, roughly the instruction mix of a
+/// typical game's main loop.
+const ROM: [u8; 24] = [
+    0x60, 0x05, // LD V0, 0x05
+    0x71, 0x01, // ADD V1, 0x01
+    0x82, 0x14, // ADD V2, V1
+    0x83, 0x26, // SHR V3, V2
+    0xA2, 0x20, // LD I, 0x220
+    0xF1, 0x1E, // ADD I, V1
+    0x34, 0x00, // SE V4, 0x00
+    0x00, 0x00, // SYS 0x000, skipped
+    0xD0, 0x15, // DRW V0, V1, 5
+    0xF0, 0x07, // LD V0, DT
+    0xF2, 0x65, // LD V2, [I]
+    0x12, 0x00, // JP 0x200
+];
+
+/// Instructions run per iteration, enough that setting up the machine doesn't dominate.
+const INSTRUCTIONS: u64 = 10_000;
+
+fn cycle(c: &mut Criterion) {
+    let chip8 = Chip8::from_bytes(&ROM).unwrap();
+
+    let mut group = c.benchmark_group("cycle");
+    // Reported as elements per second, i.e. instructions per second
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("game loop", |b| {
+        b.iter_batched_ref(
+            || chip8.clone(),
+            |chip8| {
+                for _ in 0..INSTRUCTIONS {
+                    chip8.cycle().unwrap();
+                }
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn draw(c: &mut Criterion) {
+    let sprite = [0xFF; 15];
+    let mut display = Display::new();
+
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(1));
+    for (name, wrap) in [
+        ("15-byte sprite", false),
+        ("15-byte sprite, wrapping", true),
+    ] {
+        group.bench_function(name, |b| {
+            // Straddling the bottom right corner exercises clipping or wrapping
+            b.iter(|| {
+                display.draw(
+                    black_box(60),
+                    black_box(25),
+                    black_box(&sprite),
+                    wrap,
+                    DrawMode::Xor,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cycle, draw);
+criterion_main!(benches);
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::{error, fmt};
+
+use crate::chip8::Chip8;
+
+/// A problem in assembly source, with the 1-based line it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// Assembles source in the syntax the disassembler produces into ROM bytes loaded at the usual
+/// start address, e.g.
+///
+/// ```text
+/// start:  LD V0, 0x10     ; comments run to the end of the line
+///         DRW V0, V1, 5
+///         JP start
+/// data:   DB 0x3C, 0x42
+/// ```
+///
+/// Mnemonics and operands are case-insensitive, numbers may be decimal, `0x` hex or `0b` binary,
+/// and labels can stand in for any address. `DB` emits bytes, or big-endian words for hex values
+/// written with more than two digits, which is how the disassembler lists unknown opcodes.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = Chip8::START_ADDRESS;
+
+    // The first pass only sizes statements so that labels can be used before they're defined
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let error = |message| AsmError { line, message };
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(format!("Invalid label \"{}\"", label)));
+            }
+            if labels.insert(label, address).is_some() {
+                return Err(error(format!(
+                    "Label \"{}\" is defined more than once",
+                    label
+                )));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = Statement::parse(line, text);
+        address += statement.size();
+        statements.push(statement);
+    }
+
+    let mut rom = Vec::new();
+    for statement in &statements {
+        statement
+            .encode(&labels, &mut rom)
+            .map_err(|message| AsmError {
+                line: statement.line,
+                message,
+            })?;
+    }
+
+    Ok(rom)
+}
+
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Statement<'a> {
+    line: usize,
+    text: &'a str,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+enum Operand<'a> {
+    V(u16),
+    I,
+    /// `[I]`, the memory `I` points at
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    B,
+    R,
+    /// `LONG nnnn`, the 16-bit operand of `F000`
+    Long(&'a str),
+    /// A number or label
+    Value(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    fn parse(s: &'a str) -> Self {
+        let upper = s.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::DT,
+            "ST" => Operand::ST,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::HF,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ if upper.starts_with("LONG ") => Operand::Long(s[5..].trim()),
+            _ => match upper.strip_prefix('V').map(|n| u16::from_str_radix(n, 16)) {
+                Some(Ok(x)) if upper.len() == 2 => Operand::V(x),
+                _ => Operand::Value(s),
+            },
+        }
+    }
+}
+
+impl<'a> Statement<'a> {
+    fn parse(line: usize, text: &'a str) -> Self {
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+        let operands = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        Self {
+            line,
+            text,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.iter().map(|op| db_width(op)).sum(),
+            "LD" if self
+                .operands
+                .iter()
+                .any(|op| matches!(Operand::parse(op), Operand::Long(_))) =>
+            {
+                4
+            }
+            _ => 2,
+        }
+    }
+
+    fn encode(&self, labels: &HashMap<&str, usize>, rom: &mut Vec<u8>) -> Result<(), String> {
+        let value = |s: &str, max: u16| resolve(s, labels, max);
+
+        if self.mnemonic == "DB" {
+            if self.operands.is_empty() {
+                return Err("DB needs at least one value".to_string());
+            }
+            for op in &self.operands {
+                if db_width(op) == 2 {
+                    rom.extend(value(op, 0xFFFF)?.to_be_bytes());
+                } else {
+                    rom.push(value(op, 0xFF)? as u8);
+                }
+            }
+            return Ok(());
+        }
+
+        use Operand::*;
+        let operands: Vec<Operand> = self.operands.iter().map(|op| Operand::parse(op)).collect();
+        let opcode = match (self.mnemonic.as_str(), operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [Value(n)]) => 0x00C0 | value(n, 0xF)?,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("JP", [Value(a)]) => 0x1000 | value(a, 0xFFF)?,
+            ("JP", [V(0), Value(a)]) => 0xB000 | value(a, 0xFFF)?,
+            ("CALL", [Value(a)]) => 0x2000 | value(a, 0xFFF)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+            ("SE", [V(x), Value(k)]) => 0x3000 | x << 8 | value(k, 0xFF)?,
+            ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+            ("SNE", [V(x), Value(k)]) => 0x4000 | x << 8 | value(k, 0xFF)?,
+            ("LD", [I, Long(a)]) => {
+                rom.extend([0xF0, 0x00]);
+                value(a, 0xFFFF)?
+            }
+            ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+            ("LD", [V(x), Value(k)]) => 0x6000 | x << 8 | value(k, 0xFF)?,
+            ("LD", [I, Value(a)]) => 0xA000 | value(a, 0xFFF)?,
+            ("LD", [V(x), DT]) => 0xF007 | x << 8,
+            ("LD", [V(x), K]) => 0xF00A | x << 8,
+            ("LD", [DT, V(x)]) => 0xF015 | x << 8,
+            ("LD", [ST, V(x)]) => 0xF018 | x << 8,
+            ("LD", [F, V(x)]) => 0xF029 | x << 8,
+            ("LD", [HF, V(x)]) => 0xF030 | x << 8,
+            ("LD", [B, V(x)]) => 0xF033 | x << 8,
+            ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+            ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+            ("LD", [R, V(x)]) => 0xF075 | x << 8,
+            ("LD", [V(x), R]) => 0xF085 | x << 8,
+            ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+            ("ADD", [V(x), Value(k)]) => 0x7000 | x << 8 | value(k, 0xFF)?,
+            ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+            ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+            ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+            ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+            ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+            ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+            ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+            ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+            ("RND", [V(x), Value(k)]) => 0xC000 | x << 8 | value(k, 0xFF)?,
+            ("DRW", [V(x), V(y), Value(n)]) => 0xD000 | x << 8 | y << 4 | value(n, 0xF)?,
+            ("SKP", [V(x)]) => 0xE09E | x << 8,
+            ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+            ("AUDIO", []) => 0xF002,
+            ("PLANE", [Value(n)]) => 0xF001 | value(n, 0xF)? << 8,
+            ("PITCH", [V(x)]) => 0xF03A | x << 8,
+            _ => return Err(format!("Invalid instruction \"{}\"", self.text)),
+        };
+        rom.extend(opcode.to_be_bytes());
+
+        Ok(())
+    }
+}
+
+/// Hex values with more than two digits are words, everything else a byte.
+fn db_width(s: &str) -> usize {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(digits) if digits.len() > 2 => 2,
+        _ => 1,
+    }
+}
+
+/// Parses a number or looks up a label, checking it fits in `max`.
+fn resolve(s: &str, labels: &HashMap<&str, usize>, max: u16) -> Result<u16, String> {
+    let number = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        usize::from_str_radix(binary, 2).ok()
+    } else {
+        s.parse().ok()
+    };
+
+    let value = match number {
+        Some(n) => n,
+        None if is_label(s) => *labels
+            .get(s)
+            .ok_or_else(|| format!("Unknown label \"{}\"", s))?,
+        None => return Err(format!("Invalid number \"{}\"", s)),
+    };
+
+    u16::try_from(value)
+        .ok()
+        .filter(|v| *v <= max)
+        .ok_or_else(|| format!("{} doesn't fit in {:#X}", s, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn assembles_labels_and_data() {
+        let source = "
+            start:  ld v0, 0x10     ; lowercase works too
+                    DRW V0, V1, 5
+                    JP start
+            data:   DB 0x3C, 66, 0b1
+                    LD I, data
+        ";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x10, 0xD0, 0x15, 0x12, 0x00, 0x3C, 0x42, 0x01, 0xA2, 0x06]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_the_disassembler() {
+        let opcodes: [u16; 52] = [
+            0x00E0, 0x00EE, 0x00C3, 0x00FB, 0x00FC, 0x00FE, 0x00FF, 0x12A4, 0x2ABC, 0x3A12, 0x4B34,
+            0x5120, 0x6A2F, 0x7C01, 0x8120, 0x8121, 0x8122, 0x8123, 0x8124, 0x8125, 0x8126, 0x8127,
+            0x812E, 0x9AB0, 0xA123, 0xB456, 0xC7FF, 0xD015, 0xE19E, 0xE2A1, 0xF002, 0xF201, 0xF307,
+            0xF40A, 0xF515, 0xF618, 0xF71E, 0xF829, 0xF930, 0xFA3A, 0xFB33, 0xFC55, 0xFD65, 0xFE75,
+            0xF085, 0xF000, 0x1234, 0x5121, 0xFFFF, 0x0000, 0x8008, 0xE000,
+        ];
+        let mut rom: Vec<u8> = opcodes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        rom.push(0x12);
+
+        let source: Vec<String> = disassemble(&rom).into_iter().map(|(_, _, m)| m).collect();
+        assert_eq!(assemble(&source.join("\n")).unwrap(), rom);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        assert_eq!(
+            assemble("CLS\nJP nowhere"),
+            Err(AsmError {
+                line: 2,
+                message: "Unknown label \"nowhere\"".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("LD V0, 0x100").unwrap_err().message,
+            "0x100 doesn't fit in 0xFF"
+        );
+        assert_eq!(
+            assemble("\n\nLD V0").unwrap_err(),
+            AsmError {
+                line: 3,
+                message: "Invalid instruction \"LD V0\"".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("a: CLS\na: RET").unwrap_err().message,
+            "Label \"a\" is defined more than once"
+        );
+    }
+}
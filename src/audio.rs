@@ -0,0 +1,209 @@
+use std::f32::consts::TAU;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+/// Shape of the classic CHIP-8 beep.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Waveform {
+    /// Authentic but harsh
+    #[default]
+    Square,
+    Triangle,
+    /// The softest
+    Sine,
+    Sawtooth,
+}
+
+impl Waveform {
+    /// The wave's value from -1 to 1 at `phase`, a fraction of a period from 0 to 1.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase <= 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sine => (TAU * phase).sin(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+/// Tone generator: a fixed-pitch wave for classic CHIP-8, or a looping XO-CHIP 1-bit pattern.
+struct Tone {
+    sample_rate: f32,
+    volume: f32,
+    waveform: Waveform,
+    phase_inc: f32,
+    phase: f32,
+    pattern: Option<[u8; 16]>,
+    pattern_inc: f32,
+    position: f32,
+}
+
+impl Tone {
+    const PATTERN_BITS: f32 = 128.0;
+
+    fn wave(&mut self) -> f32 {
+        let sample = self.waveform.sample(self.phase);
+        self.phase = (self.phase + self.phase_inc) % 1.0;
+        sample
+    }
+
+    fn pattern_bit(&mut self, pattern: &[u8; 16]) -> bool {
+        let bit = self.position as usize;
+        let high = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+        self.position = (self.position + self.pattern_inc) % Self::PATTERN_BITS;
+        high
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            let sample = match self.pattern {
+                Some(pattern) => {
+                    if self.pattern_bit(&pattern) {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => self.wave(),
+            };
+            *x = sample * self.volume;
+        }
+    }
+}
+
+pub(crate) struct Beeper {
+    device: AudioDevice<Tone>,
+    playing: bool,
+    pattern: Option<[u8; 16]>,
+    rate: f32,
+}
+
+impl Beeper {
+    /// Opens the audio device with the beep at `volume` percent of full scale, playing `waveform`
+    /// at `frequency` Hz.
+    pub(crate) fn new(
+        sdl_context: &sdl2::Sdl,
+        volume: u8,
+        waveform: Waveform,
+        frequency: f32,
+    ) -> Result<Self, String> {
+        let audio = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio.open_playback(None, &desired_spec, |spec| Tone {
+            sample_rate: spec.freq as f32,
+            volume: amplitude(volume),
+            waveform,
+            phase_inc: frequency / spec.freq as f32,
+            phase: 0.0,
+            pattern: None,
+            pattern_inc: 0.0,
+            position: 0.0,
+        })?;
+
+        Ok(Self {
+            device,
+            playing: false,
+            pattern: None,
+            rate: 0.0,
+        })
+    }
+
+    /// Switches to playing an XO-CHIP audio pattern at `rate` bits per second, or back to the
+    /// beep in the `--waveform` it was opened with if `pattern` is `None`.
+    pub(crate) fn set_pattern(&mut self, pattern: Option<&[u8; 16]>, rate: f32) {
+        let pattern = pattern.copied();
+        if pattern == self.pattern && rate == self.rate {
+            return;
+        }
+
+        let mut tone = self.device.lock();
+        if pattern != self.pattern {
+            tone.pattern = pattern;
+            tone.position = 0.0;
+        }
+        tone.pattern_inc = rate / tone.sample_rate;
+
+        self.pattern = pattern;
+        self.rate = rate;
+    }
+
+    pub(crate) fn set_volume(&mut self, volume: u8) {
+        self.device.lock().volume = amplitude(volume);
+    }
+
+    /// Starts or stops the tone. The device is only touched on a change so that the waveform
+    /// isn't restarted (and doesn't click) every frame.
+    pub(crate) fn set_playing(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = playing;
+    }
+}
+
+/// Sample amplitude for a volume percentage, capped at full scale so the wave never clips.
+fn amplitude(volume: u8) -> f32 {
+    volume.min(100) as f32 / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms_span_full_scale() {
+        for waveform in [
+            Waveform::Square,
+            Waveform::Triangle,
+            Waveform::Sine,
+            Waveform::Sawtooth,
+        ] {
+            let samples: Vec<f32> = (0..=100)
+                .map(|i| waveform.sample(i as f32 / 100.0))
+                .collect();
+            let max = samples.iter().copied().fold(f32::MIN, f32::max);
+            let min = samples.iter().copied().fold(f32::MAX, f32::min);
+
+            assert!(
+                (0.99..=1.0).contains(&max),
+                "{:?} peaks at {}",
+                waveform,
+                max
+            );
+            assert!(
+                (-1.0..=-0.99).contains(&min),
+                "{:?} dips to {}",
+                waveform,
+                min
+            );
+        }
+    }
+
+    #[test]
+    fn triangle_peaks_mid_period() {
+        assert_eq!(Waveform::Triangle.sample(0.0), -1.0);
+        assert_eq!(Waveform::Triangle.sample(0.25), 0.0);
+        assert_eq!(Waveform::Triangle.sample(0.5), 1.0);
+    }
+}
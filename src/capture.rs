@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8::{Chip8, Display};
+use sdl2::pixels::Color;
+
+/// Converts a screen palette into the RGBA form the library renders with.
+pub(crate) fn rgba_palette(palette: &[Color; 4]) -> [[u8; 4]; 4] {
+    palette.map(|c| [c.r, c.g, c.b, 0xFF])
+}
+
+/// A file name in `dir` stamped with the current time, so captures never overwrite each other.
+pub(crate) fn timestamped_path(dir: &Path, extension: &str) -> PathBuf {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    dir.join(format!(
+        "chip8-{}-{:03}.{}",
+        now.as_secs(),
+        now.subsec_millis(),
+        extension
+    ))
+}
+
+/// Writes the current frame to `path` as a PNG, each pixel scaled up `scale` times.
+pub(crate) fn save_screenshot(
+    chip8: &Chip8,
+    path: &Path,
+    scale: usize,
+    palette: &[Color; 4],
+) -> Result<(), String> {
+    let (width, height) = chip8.resolution();
+    let rgba = chip8.render_rgba(scale, &rgba_palette(palette));
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        (width * scale) as u32,
+        (height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&rgba).map_err(|e| e.to_string())
+}
+
+/// Records frames into an animated GIF. Frames are always rendered at the size of the SUPER-CHIP
+/// hi-res mode, so a game switching resolution mid-recording keeps the same image size.
+pub(crate) struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    path: PathBuf,
+    scale: usize,
+    palette: [[u8; 4]; 4],
+    /// Emulated 60Hz frames per recorded frame
+    interval: u32,
+    /// Emulated frames seen so far
+    frames: u32,
+}
+
+impl Recorder {
+    pub(crate) fn new(
+        path: PathBuf,
+        fps: u32,
+        scale: usize,
+        palette: &[Color; 4],
+    ) -> Result<Self, String> {
+        let (width, height) = (
+            Display::HIRES_VIDEO_WIDTH * scale,
+            Display::HIRES_VIDEO_HEIGHT * scale,
+        );
+        let global_palette: Vec<u8> = palette.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(file),
+            width as u16,
+            height as u16,
+            &global_palette,
+        )
+        .map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            encoder,
+            path,
+            scale,
+            palette: rgba_palette(palette),
+            interval: Chip8::TIMER_FREQUENCY / fps.clamp(1, Chip8::TIMER_FREQUENCY),
+            frames: 0,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Called once per emulated frame, keeping every `interval`th one.
+    pub(crate) fn capture(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let frame = self.frames;
+        self.frames += 1;
+        if !frame.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+
+        let scale = self.scale * Display::HIRES_VIDEO_WIDTH / chip8.resolution().0;
+        let rgba = chip8.render_rgba(scale, &self.palette);
+        // Every pixel came from the palette, so map them back to their palette indices
+        let indices: Vec<u8> = rgba
+            .chunks_exact(4)
+            .map(|pixel| self.palette.iter().position(|c| c == pixel).unwrap() as u8)
+            .collect();
+
+        let frame = gif::Frame {
+            width: (Display::HIRES_VIDEO_WIDTH * self.scale) as u16,
+            height: (Display::HIRES_VIDEO_HEIGHT * self.scale) as u16,
+            buffer: indices.into(),
+            delay: self.frame_delay(frame),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(|e| e.to_string())
+    }
+
+    /// Delay in hundredths of a second from emulated `frame` until the next recorded one. GIF
+    /// delays can't express 60Hz frames exactly, so they're rounded such that the total stays in
+    /// sync.
+    fn frame_delay(&self, frame: u32) -> u16 {
+        let centiseconds =
+            |frames: u32| (frames * 100 + Chip8::TIMER_FREQUENCY / 2) / Chip8::TIMER_FREQUENCY;
+
+        (centiseconds(frame + self.interval) - centiseconds(frame)) as u16
+    }
+
+    /// Writes the end of the GIF, returning where it was saved.
+    pub(crate) fn finish(self) -> Result<PathBuf, String> {
+        self.encoder.into_inner().map_err(|e| e.to_string())?;
+
+        Ok(self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_scaled_png() {
+        let chip8 = Chip8::from_bytes(&[]).unwrap();
+        let path = timestamped_path(&std::env::temp_dir(), "png");
+        let palette = [Color::BLACK, Color::WHITE, Color::GRAY, Color::GREY];
+
+        save_screenshot(&chip8, &path, 3, &palette).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), (64 * 3, 32 * 3));
+    }
+
+    #[test]
+    fn records_gif_frames_at_the_chosen_rate() {
+        let chip8 = Chip8::from_bytes(&[]).unwrap();
+        let path = timestamped_path(&std::env::temp_dir(), "gif");
+        let palette = [Color::BLACK, Color::WHITE, Color::GRAY, Color::GREY];
+
+        let mut recorder = Recorder::new(path, 20, 1, &palette).unwrap();
+        for _ in 0..6 {
+            recorder.capture(&chip8).unwrap();
+        }
+        let path = recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 64));
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(delays, [5, 5]);
+    }
+}
@@ -1,15 +1,20 @@
-use std::fs::File;
-use std::io::{self, Read};
+use std::fs;
+use std::ops::Range;
 
 use log::debug;
 
 use crate::display::Display;
+use crate::error::Chip8Error;
+use crate::instruction::{decode, Instruction};
 use crate::lsfr::Lsfr;
+use crate::quirks::Quirks;
+use crate::rng::{AllOnes, Rng};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chip8 {
     registers: [u8; 16],
-    memory: [u8; Self::MEMORY_SIZE],
+    memory: Vec<u8>,
     index: usize,
     pc: usize,
     stack: [u16; 16],
@@ -18,19 +23,137 @@ pub struct Chip8 {
     sound_timer: u8,
     keypad: [u8; 16],
     display: Display,
-    lsfr: Lsfr,
+    /// Saved separately in snapshots, through [`Rng::state`]
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_rng"))]
+    rng: Box<dyn Rng>,
+    rpl_flags: [u8; 8],
+    /// XO-CHIP 1-bit audio pattern, `None` until one is uploaded with `F002`
+    pattern_buffer: Option<[u8; 16]>,
+    pitch: u8,
+    quirks: Quirks,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    /// Instructions executed since power-on
+    cycles: u64,
+    /// Where the ROM is loaded and execution starts, normally [`Chip8::START_ADDRESS`]
+    start_address: usize,
+    /// Progress of an `Fx0A` waiting for a key
+    key_wait: KeyWait,
+    /// Keys that went down since `Fx0A` started waiting, one bit per key
+    key_presses: u16,
+    /// A `Dxyn` ran this frame under the display-wait quirk, cleared by `tick_timers`
+    vblank_wait: bool,
+    /// How often each kind of instruction has run, indexed by [`Instruction::kind`], if enabled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    opcode_profile: Option<Box<[u64; Instruction::KINDS]>>,
+}
+
+/// Machines are equal when they'd run on identically: the display is compared by what it shows,
+/// the random number generators by [`Rng::state`], and the opcode profile is left out.
+impl PartialEq for Chip8 {
+    fn eq(&self, other: &Self) -> bool {
+        // Destructured so a new field can't be forgotten here
+        let Self {
+            registers,
+            memory,
+            index,
+            pc,
+            stack,
+            sp,
+            delay_timer,
+            sound_timer,
+            keypad,
+            display,
+            rng,
+            rpl_flags,
+            pattern_buffer,
+            pitch,
+            quirks,
+            unknown_opcode_policy,
+            cycles,
+            start_address,
+            key_wait,
+            key_presses,
+            vblank_wait,
+            opcode_profile: _,
+        } = self;
+
+        *registers == other.registers
+            && *memory == other.memory
+            && *index == other.index
+            && *pc == other.pc
+            && *stack == other.stack
+            && *sp == other.sp
+            && *delay_timer == other.delay_timer
+            && *sound_timer == other.sound_timer
+            && *keypad == other.keypad
+            && *display == other.display
+            && rng.state() == other.rng.state()
+            && *rpl_flags == other.rpl_flags
+            && *pattern_buffer == other.pattern_buffer
+            && *pitch == other.pitch
+            && *quirks == other.quirks
+            && *unknown_opcode_policy == other.unknown_opcode_policy
+            && *cycles == other.cycles
+            && *start_address == other.start_address
+            && *key_wait == other.key_wait
+            && *key_presses == other.key_presses
+            && *vblank_wait == other.vblank_wait
+    }
+}
+
+/// What `cycle` does when it meets an opcode no supported interpreter defines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnknownOpcodePolicy {
+    /// Panic, as the emulator originally did
+    Panic,
+    /// Skip over it as if it were a 2-byte no-op, e.g. for ROMs that run into junk data
+    Nop,
+    /// Stop with [`Chip8Error::UnknownOpcode`], leaving `pc` on the opcode
+    #[default]
+    Halt,
+}
+
+/// `Fx0A` waits for a key to be pressed and then released, like the COSMAC VIP, so a key
+/// already held when it starts doesn't count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum KeyWait {
+    Idle,
+    Press,
+    Release(u8),
 }
 
 enum PC {
     Next,
     Skip,
+    /// Step over a double-width instruction
+    NextLong,
     Jump(usize),
 }
 
+fn default_rng() -> Box<dyn Rng> {
+    Box::new(Lsfr::new())
+}
+
 impl Chip8 {
-    const MEMORY_SIZE: usize = 4096;
-    const START_ADDRESS: usize = 0x200;
+    /// Rate at which the delay and sound timers count down, independent of the CPU speed.
+    pub const TIMER_FREQUENCY: u32 = 60;
+    /// Memory of the original CHIP-8 interpreters, and the default.
+    pub const MEMORY_SIZE: usize = 0x1000;
+    /// Memory XO-CHIP programs expect, reachable through the 16-bit `F000` index load.
+    pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+    /// XO-CHIP pitch at which audio patterns play back at 4000 bits per second
+    const DEFAULT_PITCH: u8 = 64;
+    /// Address ROMs are loaded at and execution starts from.
+    pub const START_ADDRESS: usize = 0x200;
     const FONTSET_START_ADDRESS: usize = 0x50;
+    /// Longest sprite `Dxyn` reads: a 16x16 SUPER-CHIP sprite in each XO-CHIP plane
+    const MAX_SPRITE_BYTES: usize = 32 * Display::PLANES;
+    /// Bound on `pc` and `I` in a restored state, far past any memory but nowhere near
+    /// overflowing the arithmetic done on them
+    #[cfg(feature = "serde")]
+    const ADDRESS_LIMIT: usize = 1 << 24;
     const FONTSET: [u8; 80] = [
         0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
         0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -49,25 +172,74 @@ impl Chip8 {
         0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
         0xF0, 0x80, 0xF0, 0x80, 0x80, // F
     ];
+    const BIG_FONTSET_START_ADDRESS: usize = Self::FONTSET_START_ADDRESS + Self::FONTSET.len();
+    /// SUPER-CHIP 8x10 digits, used by `Fx30`
+    const BIG_FONTSET: [u8; 160] = [
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ];
 
-    const fn start_memory() -> [u8; Self::MEMORY_SIZE] {
-        let mut memory = [0; Self::MEMORY_SIZE];
-
-        let mut i = 0;
-        while i < Self::FONTSET.len() {
-            memory[i + Self::FONTSET_START_ADDRESS] = Self::FONTSET[i];
-            i += 1;
-        }
+    fn start_memory(size: usize) -> Vec<u8> {
+        let mut memory = vec![0; size];
+        memory[Self::FONTSET_START_ADDRESS..Self::BIG_FONTSET_START_ADDRESS]
+            .copy_from_slice(&Self::FONTSET);
+        memory[Self::BIG_FONTSET_START_ADDRESS
+            ..Self::BIG_FONTSET_START_ADDRESS + Self::BIG_FONTSET.len()]
+            .copy_from_slice(&Self::BIG_FONTSET);
 
         memory
     }
 
-    pub fn read_rom(filename: &str) -> io::Result<Self> {
-        let mut f = File::open(filename)?;
-        let mut memory = Self::start_memory();
+    pub fn read_rom(filename: &str) -> Result<Self, Chip8Error> {
+        Self::read_rom_with_memory_size(filename, Self::MEMORY_SIZE)
+    }
 
-        let n = f.read(&mut memory[Self::START_ADDRESS..])?;
-        debug!("Read {} bytes", n);
+    pub fn read_rom_with_memory_size(
+        filename: &str,
+        memory_size: usize,
+    ) -> Result<Self, Chip8Error> {
+        let rom = fs::read(filename)?;
+        debug!("Read {} bytes", rom.len());
+        #[cfg(feature = "gzip")]
+        let rom = crate::gzip::decompress_rom(rom)?;
+
+        Self::from_bytes_with_memory_size(&rom, memory_size)
+    }
+
+    pub fn from_bytes(rom: &[u8]) -> Result<Self, Chip8Error> {
+        Self::from_bytes_with_memory_size(rom, Self::MEMORY_SIZE)
+    }
+
+    /// Loads `rom` into `memory_size` bytes of memory, e.g. [`Chip8::XO_CHIP_MEMORY_SIZE`]. The
+    /// memory must extend past [`Chip8::START_ADDRESS`].
+    pub fn from_bytes_with_memory_size(rom: &[u8], memory_size: usize) -> Result<Self, Chip8Error> {
+        if memory_size <= Self::START_ADDRESS {
+            return Err(Chip8Error::InvalidStartAddress(Self::START_ADDRESS));
+        }
+        let max = memory_size - Self::START_ADDRESS;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+
+        let mut memory = Self::start_memory(memory_size);
+        memory[Self::START_ADDRESS..(Self::START_ADDRESS + rom.len())].copy_from_slice(rom);
 
         Ok(Self {
             registers: [0; 16],
@@ -80,68 +252,295 @@ impl Chip8 {
             sound_timer: 0,
             keypad: [0; 16],
             display: Display::new(),
-            lsfr: Lsfr::new(),
+            rng: default_rng(),
+            rpl_flags: [0; 8],
+            pattern_buffer: None,
+            pitch: Self::DEFAULT_PITCH,
+            quirks: Quirks::default(),
+            unknown_opcode_policy: UnknownOpcodePolicy::default(),
+            cycles: 0,
+            start_address: Self::START_ADDRESS,
+            key_wait: KeyWait::Idle,
+            key_presses: 0,
+            vblank_wait: false,
+            opcode_profile: None,
         })
     }
 
-    fn gen_random(&mut self) -> u8 {
-        self.lsfr.gen()
+    /// Switches to a different set of interpreter quirks, e.g. straight after loading a ROM.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Seeds the random number generator used by `Cxkk`, making its sequence reproducible for a
+    /// given seed. The generator is a maximal-length 16-bit LFSR, so every seed lies on the same
+    /// cycle of 65535 states and only changes where in it the sequence starts. 0 can't seed the
+    /// generator and selects the default seed instead. Replaces any generator set with
+    /// [`Chip8::with_rng`].
+    pub fn with_seed(mut self, seed: u16) -> Self {
+        self.rng = Box::new(Lsfr::with_seed(seed));
+        self
     }
 
-    fn process_instruction(&mut self, instruction: u16) {
-        let x = instruction.to_be_bytes();
-        let o1: u8 = x[0] >> 4;
-        let o2: u8 = x[0] & 0xf;
-        let o3: u8 = x[1] >> 4;
-        let o4: u8 = x[1] & 0xf;
+    /// Swaps the LFSR `Cxkk` draws from for another generator.
+    pub fn with_rng(mut self, rng: Box<dyn Rng>) -> Self {
+        self.rng = rng;
+        self
+    }
 
-        #[inline(always)]
-        fn nnn(n1: u8, n2: u8, n3: u8) -> u16 {
-            ((n1 as u16) << 8) + ((n2 as u16) << 4) + n3 as u16
+    /// Takes the randomness out of `Cxkk` altogether, so `RND Vx, kk` always loads `kk`. Meant as
+    /// a testing aid for ROMs whose frames would otherwise depend on the random numbers drawn; no
+    /// game plays properly like this.
+    pub fn without_randomness(self) -> Self {
+        self.with_rng(Box::new(AllOnes))
+    }
+
+    /// Replaces the built-in 4x5 hex digit glyphs `Fx29` points at with `fontset`, 5 bytes per
+    /// digit from 0 to F. The SUPER-CHIP large digits are unaffected.
+    pub fn with_fontset(mut self, fontset: &[u8; 80]) -> Self {
+        self.memory[Self::FONTSET_START_ADDRESS..Self::FONTSET_START_ADDRESS + fontset.len()]
+            .copy_from_slice(fontset);
+        self
+    }
+
+    /// Counts how many times each kind of instruction runs, for [`Chip8::opcode_profile`]. Off by
+    /// default, as it costs a little on every instruction.
+    pub fn with_opcode_profile(mut self) -> Self {
+        self.opcode_profile = Some(Box::new([0; Instruction::KINDS]));
+        self
+    }
+
+    /// How many times each kind of instruction has run, indexed by [`Instruction::kind`], if
+    /// enabled with [`Chip8::with_opcode_profile`]. Survives resets and loading snapshots.
+    pub fn opcode_profile(&self) -> Option<&[u64; Instruction::KINDS]> {
+        self.opcode_profile.as_deref()
+    }
+
+    pub fn with_unknown_opcode_policy(mut self, policy: UnknownOpcodePolicy) -> Self {
+        self.unknown_opcode_policy = policy;
+        self
+    }
+
+    /// Moves the ROM to `address` and starts execution there, e.g. 0x600 for ETI-660 programs.
+    /// The address must be past the font tables. Only the ROM up to its last non-zero byte is
+    /// moved, so trailing zeros don't count towards whether it still fits.
+    pub fn with_start_address(mut self, address: usize) -> Result<Self, Chip8Error> {
+        let fonts_end = Self::BIG_FONTSET_START_ADDRESS + Self::BIG_FONTSET.len();
+        if address < fonts_end || address >= self.memory.len() {
+            return Err(Chip8Error::InvalidStartAddress(address));
         }
-        #[inline(always)]
-        fn var(x1: u8, x2: u8) -> u8 {
-            ((x1 as u8) << 4) + x2 as u8
+
+        let old = self.start_address;
+        let len = self.memory[old..]
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(0, |i| i + 1);
+        let max = self.memory.len() - address;
+        if len > max {
+            return Err(Chip8Error::RomTooLarge { size: len, max });
+        }
+
+        let rom = self.memory[old..old + len].to_vec();
+        self.memory[old..old + len].fill(0);
+        self.memory[address..address + len].copy_from_slice(&rom);
+        self.start_address = address;
+        self.pc = address;
+        Ok(self)
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.start_address
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Swaps in a new version of the ROM at the start address and resets, keeping the quirks,
+    /// memory size and fontset the machine was set up with.
+    pub fn reload(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - self.start_address;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
         }
 
-        debug!("instruction: {:x}{:x}{:x}{:x}", o1, o2, o3, o4);
+        self.memory[self.start_address..].fill(0);
+        self.memory[self.start_address..self.start_address + rom.len()].copy_from_slice(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Returns the machine to its power-on state, keeping the loaded ROM and fontset in memory.
+    /// The RPL user flags are persistent storage, so they survive a reset too.
+    pub fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.index = 0;
+        self.pc = self.start_address;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.keypad = [0; 16];
+        self.display = Display::new();
+        self.pattern_buffer = None;
+        self.pitch = Self::DEFAULT_PITCH;
+        self.cycles = 0;
+        self.key_wait = KeyWait::Idle;
+        self.key_presses = 0;
+        self.vblank_wait = false;
+    }
+
+    /// Serialises the full machine state, including the RNG, into a compact snapshot.
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        bincode::serialize(&(self, self.rng.state())).expect("Chip8 state is always serialisable")
+    }
+
+    /// Restores a snapshot taken with [`Chip8::save_state`]. The display is marked dirty so the
+    /// restored frame gets drawn. The machine keeps its own generator, moved to the saved
+    /// position, and its opcode profile.
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let (mut restored, rng_state): (Self, u64) =
+            bincode::deserialize(state).map_err(Chip8Error::InvalidState)?;
+        restored.check_state().map_err(Chip8Error::CorruptState)?;
+        std::mem::swap(&mut restored.rng, &mut self.rng);
+        std::mem::swap(&mut restored.opcode_profile, &mut self.opcode_profile);
+        *self = restored;
+        self.rng.set_state(rng_state);
+        self.display.force_redraw();
+
+        Ok(())
+    }
 
-        let pc_change = match (o1, o2, o3, o4) {
+    /// Checks a decoded snapshot for values the machine would panic on later, such as a stack
+    /// pointer past the stack, so a damaged or hand-edited state file is rejected when loading.
+    #[cfg(feature = "serde")]
+    fn check_state(&self) -> Result<(), &'static str> {
+        if self.memory.len() <= Self::START_ADDRESS {
+            return Err("memory ends before the start address");
+        }
+        if self.start_address >= self.memory.len() {
+            return Err("start address is past the end of memory");
+        }
+        if self.sp > self.stack.len() {
+            return Err("stack pointer is past the end of the stack");
+        }
+        if self.pc > Self::ADDRESS_LIMIT || self.index > Self::ADDRESS_LIMIT {
+            return Err("PC or I is out of range");
+        }
+        if matches!(self.key_wait, KeyWait::Release(key) if key as usize >= self.keypad.len()) {
+            return Err("waiting for a key that doesn't exist");
+        }
+        if !self.display.is_valid() {
+            return Err("display holds pixels in planes that don't exist");
+        }
+
+        Ok(())
+    }
+
+    fn gen_random(&mut self) -> u8 {
+        self.rng.next_byte()
+    }
+
+    /// The register `8xy6`/`8xyE` shift, which depends on [`Quirks::shift_uses_vy`].
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y as usize]
+        } else {
+            self.registers[x as usize]
+        }
+    }
+
+    fn process_instruction(&mut self, instruction: u16) -> Result<(), Chip8Error> {
+        debug!("instruction: {:04x}", instruction);
+
+        let decoded = decode(instruction);
+
+        let pc_change = match decoded {
             // 00E0 - CLS
-            (0x0, 0x0, 0xE, 0x0) => {
+            Instruction::Cls => {
                 debug!("00E0 - CLS");
 
-                self.display.clear();
+                self.display.clear_planes();
+                PC::Next
+            }
+            // 00CN - SCD nibble
+            Instruction::Scd { n } => {
+                debug!("00CN - SCD {:x}", n);
+
+                self.display.scroll_down(n as usize);
+                PC::Next
+            }
+            // 00FB - SCR
+            Instruction::Scr => {
+                debug!("00FB - SCR");
+
+                self.display.scroll_right();
+                PC::Next
+            }
+            // 00FC - SCL
+            Instruction::Scl => {
+                debug!("00FC - SCL");
+
+                self.display.scroll_left();
+                PC::Next
+            }
+            // 00FE - LOW
+            Instruction::Low => {
+                debug!("00FE - LOW");
+
+                self.display.set_hires(false);
+                PC::Next
+            }
+            // 00FF - HIGH
+            Instruction::High => {
+                debug!("00FF - HIGH");
+
+                self.display.set_hires(true);
                 PC::Next
             }
             // 00EE - RET
-            (0x0, 0x0, 0xE, 0xE) => {
+            Instruction::Ret => {
                 debug!("00EE - RET");
 
-                let pc = self.stack[self.sp as usize - 1] as usize;
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow);
+                }
+                let pc = self.stack[self.sp - 1] as usize;
                 self.sp -= 1;
                 PC::Jump(pc + 2)
             }
             // 1nnn - JP addr
-            (0x1, n1, n2, n3) => {
-                let nnn = nnn(n1, n2, n3) as usize;
+            Instruction::JpAddr(nnn) => {
+                let nnn = nnn as usize;
                 debug!("1nnn - JP {:x}", nnn);
 
                 PC::Jump(nnn)
             }
             // 2nnn - CALL addr
-            (0x2, n1, n2, n3) => {
-                let nnn = nnn(n1, n2, n3) as usize;
+            Instruction::CallAddr(nnn) => {
+                let nnn = nnn as usize;
                 debug!("2nnn - CALL {:x}", nnn);
 
+                if self.sp >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow);
+                }
                 self.stack[self.sp] = self.pc as u16;
                 self.sp += 1;
                 PC::Jump(nnn)
             }
             // 3xkk - SE Vx, byte
-            (0x3, x, k1, k2) => {
+            Instruction::SeVxByte { x, kk } => {
                 let vx = self.registers[x as usize];
-                let kk = var(k1, k2);
                 debug!("3xkk - SE V{:x} ({:x}) {:x}", x, vx, kk);
 
                 if vx == kk {
@@ -151,9 +550,8 @@ impl Chip8 {
                 }
             }
             // 4xkk - SNE Vx, byte
-            (0x4, x, k1, k2) => {
+            Instruction::SneVxByte { x, kk } => {
                 let vx = self.registers[x as usize];
-                let kk = var(k1, k2);
                 debug!("4xkk - SNE V{:x} ({:x}) {:x}", x, vx, kk);
 
                 if vx != kk {
@@ -163,7 +561,7 @@ impl Chip8 {
                 }
             }
             // 5xy0 - SE Vx, Vy
-            (0x5, x, y, 0x0) => {
+            Instruction::SeVxVy { x, y } => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 debug!("5xy0 - SE V{:x} ({:x}) V{:x} ({:x})", x, vx, y, vy);
@@ -175,8 +573,7 @@ impl Chip8 {
                 }
             }
             // 6xkk - LD Vx, byte
-            (0x6, x, k1, k2) => {
-                let kk = var(k1, k2);
+            Instruction::LdVxByte { x, kk } => {
                 debug!(
                     "6xkk - LD V{:x} ({:x}) {:x}",
                     x, self.registers[x as usize], kk
@@ -186,8 +583,7 @@ impl Chip8 {
                 PC::Next
             }
             // 7xkk - ADD Vx, byte
-            (0x7, x, k1, k2) => {
-                let kk = var(k1, k2);
+            Instruction::AddVxByte { x, kk } => {
                 debug!(
                     "7xkk - ADD V{:x} ({:x}) {:x}",
                     x, self.registers[x as usize], kk
@@ -197,7 +593,7 @@ impl Chip8 {
                 PC::Next
             }
             // 8xy0 - LD Vx, Vy
-            (0x8, x, y, 0x0) => {
+            Instruction::LdVxVy { x, y } => {
                 debug!(
                     "8xy0 - LD V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
@@ -207,36 +603,45 @@ impl Chip8 {
                 PC::Next
             }
             // 8xy1 - OR Vx, Vy
-            (0x8, x, y, 0x1) => {
+            Instruction::OrVxVy { x, y } => {
                 debug!(
                     "8xy1 - OR V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
                 );
                 self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
+                    self.registers[0xF] = 0;
+                }
                 PC::Next
             }
             // 8xy2 - AND Vx, Vy
-            (0x8, x, y, 0x2) => {
+            Instruction::AndVxVy { x, y } => {
                 debug!(
                     "8xy2 - AND V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
                 );
 
                 self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
+                    self.registers[0xF] = 0;
+                }
                 PC::Next
             }
             // 8xy3 - XOR Vx, Vy
-            (0x8, x, y, 0x3) => {
+            Instruction::XorVxVy { x, y } => {
                 debug!(
                     "8xy3 - XOR V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
                 );
 
                 self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
+                    self.registers[0xF] = 0;
+                }
                 PC::Next
             }
             // 8xy4 - ADD Vx, Vy
-            (0x8, x, y, 0x4) => {
+            Instruction::AddVxVy { x, y } => {
                 debug!(
                     "8xy4 - ADD V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
@@ -254,7 +659,7 @@ impl Chip8 {
                 PC::Next
             }
             // 8xy5 - SUB Vx, Vy
-            (0x8, x, y, 0x5) => {
+            Instruction::SubVxVy { x, y } => {
                 debug!(
                     "8xy5 - SUB V{:x} ({:x}), V{:x} ({:x})",
                     x, self.registers[x as usize], y, self.registers[y as usize]
@@ -273,16 +678,17 @@ impl Chip8 {
                 PC::Next
             }
             // 8xy6 - SHR Vx
-            (0x8, x, _y, 0x6) => {
+            Instruction::ShrVxVy { x, y } => {
                 debug!("8xy6 - SHR V{:x} ({:x})", x, self.registers[x as usize]);
 
-                self.registers[0xF] = self.registers[x as usize] & 0x1;
-                self.registers[x as usize] /= 2;
+                let value = self.shift_source(x, y);
+                self.registers[x as usize] = value >> 1;
+                self.registers[0xF] = value & 0x1;
 
                 PC::Next
             }
             // 8xy7 - SUBN Vx, Vy
-            (0x8, x, y, 0x7) => {
+            Instruction::SubnVxVy { x, y } => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 debug!("8xy7 - SUBN V{:x} ({:x}), V{:x} ({:x})", x, vx, y, vy);
@@ -299,19 +705,20 @@ impl Chip8 {
                 PC::Next
             }
             // 8xyE - SHL VX {, Vy}
-            (0x8, x, _y, 0xE) => {
+            Instruction::ShlVxVy { x, y } => {
                 debug!(
                     "8xyE - SHL V{:x} ({:x}) {{, Vy}}",
                     x, self.registers[x as usize]
                 );
 
-                self.registers[0xF] = self.registers[x as usize] >> 7;
-                self.registers[x as usize] = self.registers[x as usize].overflowing_mul(2).0;
+                let value = self.shift_source(x, y);
+                self.registers[x as usize] = value << 1;
+                self.registers[0xF] = value >> 7;
 
                 PC::Next
             }
             // 9xy0 - SNE Vx, Vy
-            (0x9, x, y, 0x0) => {
+            Instruction::SneVxVy { x, y } => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 debug!("9xy0 - SNE V{:x} ({:x}), V{:x} ({:x})", x, vx, y, vy);
@@ -323,23 +730,28 @@ impl Chip8 {
                 }
             }
             // Annn - LD I, addr
-            (0xA, n1, n2, n3) => {
-                let nnn = nnn(n1, n2, n3) as usize;
+            Instruction::LdIAddr(nnn) => {
+                let nnn = nnn as usize;
                 debug!("Annn - LD {:x}, {:x}", self.index, nnn);
 
                 self.index = nnn;
                 PC::Next
             }
-            // Bnnn - LD V0, addr
-            (0xB, n1, n2, n3) => {
-                let nnn = nnn(n1, n2, n3) as usize;
-                debug!("Bnnn - LD V0, {:x}", nnn);
-
-                PC::Jump(nnn)
+            // Bnnn - JP V0, addr
+            Instruction::JpV0Addr(nnn) => {
+                let nnn = nnn as usize;
+                debug!("Bnnn - JP V0, {:x}", nnn);
+
+                // The SUPER-CHIP reads this as Bxnn and offsets by Vx instead
+                let offset = if self.quirks.jump_with_offset_uses_vx {
+                    self.registers[nnn >> 8]
+                } else {
+                    self.registers[0x0]
+                };
+                PC::Jump(nnn + offset as usize)
             }
             // Cxkk - RND Vx, byte
-            (0xC, x, k1, k2) => {
-                let kk = var(k1, k2);
+            Instruction::RndVxByte { x, kk } => {
                 debug!(
                     "Cxkk - RND V{:x} ({:x}), {:x}",
                     x, self.registers[x as usize], kk
@@ -349,7 +761,7 @@ impl Chip8 {
                 PC::Next
             }
             // Dxyn - DRW Vx, Vy, nibble
-            (0xD, x, y, n) => {
+            Instruction::DrwVxVyN { x, y, n } => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 debug!(
@@ -357,36 +769,88 @@ impl Chip8 {
                     x, vx, y, vy, n
                 );
 
-                let mem_start = self.index as usize;
-                let bytes = &self.memory[mem_start..(mem_start + n as usize)].to_vec();
-
-                self.registers[0xF] = self.display.draw(vx as usize, vy as usize, bytes);
+                // XO-CHIP stores a full sprite for each selected plane, one after another
+                let planes = self.display.planes().count_ones() as usize;
+                let mut buffer = [0; Self::MAX_SPRITE_BYTES];
+                let collided_rows = if n == 0 {
+                    // SUPER-CHIP 16x16 sprite, two bytes per row
+                    let bytes = self.sprite_bytes(&mut buffer, 32 * planes);
+                    self.display.draw_wide(
+                        vx as usize,
+                        vy as usize,
+                        bytes,
+                        self.quirks.display_wraps,
+                        self.quirks.draw_mode,
+                    )
+                } else {
+                    let bytes = self.sprite_bytes(&mut buffer, n as usize * planes);
+                    self.display.draw(
+                        vx as usize,
+                        vy as usize,
+                        bytes,
+                        self.quirks.display_wraps,
+                        self.quirks.draw_mode,
+                    )
+                };
+
+                // The SUPER-CHIP reports the number of colliding rows in hi-res mode
+                self.registers[0xF] = if self.display.is_hires() {
+                    collided_rows
+                } else {
+                    (collided_rows > 0) as u8
+                };
+                self.vblank_wait = self.quirks.display_wait;
                 PC::Next
             }
             // Ex9E - SKP Vx
-            (0xE, x, 0x9, 0xE) => {
+            Instruction::SkpVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Ex9E - SKP V{:x} ({:x})", x, vx);
 
-                if self.keypad[vx as usize] == 1 {
+                // Only the low nibble selects a key
+                if self.keypad[vx as usize & 0xF] == 1 {
                     PC::Skip
                 } else {
                     PC::Next
                 }
             }
             // ExA1 - SKNP Vx
-            (0xE, x, 0xA, 0x1) => {
+            Instruction::SknpVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("ExA1 - SKNP V{:x} ({:x})", x, vx);
 
-                if self.keypad[vx as usize] != 1 {
+                if self.keypad[vx as usize & 0xF] != 1 {
                     PC::Skip
                 } else {
                     PC::Next
                 }
             }
+            // F000 nnnn - LD I, long addr
+            Instruction::LdILong => {
+                let addr = self.word_at(self.pc + 2)? as usize;
+                debug!("F000 - LD I, LONG {:x}", addr);
+
+                self.index = addr;
+                PC::NextLong
+            }
+            // F002 - AUDIO
+            Instruction::Audio => {
+                debug!("F002 - AUDIO {:x}", self.index);
+
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[self.checked_range(self.index, 16)?]);
+                self.pattern_buffer = Some(pattern);
+                PC::Next
+            }
+            // Fn01 - PLANE n
+            Instruction::Plane { n } => {
+                debug!("Fn01 - PLANE {:x}", n);
+
+                self.display.select_planes(n as u32);
+                PC::Next
+            }
             // Fx07 - LD Vx, DT
-            (0xF, x, 0x0, 0x7) => {
+            Instruction::LdVxDt { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx07 - LD V{:x} ({:x}), DT", x, vx);
 
@@ -394,28 +858,30 @@ impl Chip8 {
                 PC::Next
             }
             // Fx0A - LD Vx, K
-            (0xF, x, 0x0, 0xA) => {
+            Instruction::LdVxK { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx0A - LD V{:x} ({:x}), K", x, vx);
 
-                let mut pressed = false;
-
-                for (n, &i) in self.keypad.iter().enumerate() {
-                    if i == 1 {
-                        self.registers[x as usize] = n as u8;
-                        pressed = true;
-                        break;
+                match self.key_wait {
+                    KeyWait::Idle => {
+                        self.key_presses = 0;
+                        self.key_wait = KeyWait::Press;
+                        PC::Jump(self.pc)
                     }
-                }
-
-                if pressed {
-                    PC::Next
-                } else {
-                    PC::Jump(self.pc)
+                    KeyWait::Press if self.key_presses != 0 => {
+                        self.key_wait = KeyWait::Release(self.key_presses.trailing_zeros() as u8);
+                        PC::Jump(self.pc)
+                    }
+                    KeyWait::Release(key) if self.keypad[key as usize] == 0 => {
+                        self.registers[x as usize] = key;
+                        self.key_wait = KeyWait::Idle;
+                        PC::Next
+                    }
+                    _ => PC::Jump(self.pc),
                 }
             }
             // Fx15 - LD DT, Vx
-            (0xF, x, 0x1, 0x5) => {
+            Instruction::LdDtVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx15 - LD DT, V{:x} ({:x})", x, vx);
 
@@ -424,7 +890,7 @@ impl Chip8 {
                 PC::Next
             }
             // Fx18 - LD ST, Vx
-            (0xF, x, 0x1, 0x8) => {
+            Instruction::LdStVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx18 - LD ST, V{:x} ({:x})", x, vx);
 
@@ -433,16 +899,20 @@ impl Chip8 {
                 PC::Next
             }
             // Fx1E - ADD I, Vx
-            (0xF, x, 0x1, 0xE) => {
+            Instruction::AddIVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx1E - ADD {:x}, V{:x} ({:x})", self.index, x, vx);
 
                 self.index += vx as usize;
+                if self.quirks.index_overflow_sets_vf {
+                    self.registers[0xF] = (self.index > 0xFFF) as u8;
+                    self.index &= 0xFFF;
+                }
 
                 PC::Next
             }
             // Fx29 - LD F, Vx
-            (0xF, x, 0x2, 0x9) => {
+            Instruction::LdFVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx29 - LD F, V{:x} ({:x})", x, vx);
 
@@ -451,51 +921,167 @@ impl Chip8 {
 
                 PC::Next
             }
+            // Fx30 - LD HF, Vx
+            Instruction::LdHfVx { x } => {
+                let vx = self.registers[x as usize];
+                debug!("Fx30 - LD HF, V{:x} ({:x})", x, vx);
+
+                self.index = Self::BIG_FONTSET_START_ADDRESS + (10 * (vx as usize & 0xF));
+
+                PC::Next
+            }
+            // Fx3A - PITCH Vx
+            Instruction::PitchVx { x } => {
+                let vx = self.registers[x as usize];
+                debug!("Fx3A - PITCH V{:x} ({:x})", x, vx);
+
+                self.pitch = vx;
+                PC::Next
+            }
             // Fx33 - LD B, Vx
-            (0xF, x, 0x3, 0x3) => {
+            Instruction::LdBVx { x } => {
                 let vx = self.registers[x as usize];
                 debug!("Fx33 - LD B, V{:x} ({:x})", x, vx);
 
-                self.memory[self.index] = (vx / 100) as u8 % 10;
-                self.memory[self.index + 1] = (vx / 10) as u8 % 10;
-                self.memory[self.index + 2] = vx % 10;
+                let range = self.checked_range(self.index, 3)?;
+                let digits = &mut self.memory[range];
+                digits[0] = (vx / 100) % 10;
+                digits[1] = (vx / 10) % 10;
+                digits[2] = vx % 10;
 
                 PC::Next
             }
             // Fx55 - LD [I], Vx
-            (0xF, x, 0x5, 0x5) => {
+            Instruction::LdIVx { x } => {
                 debug!("Fx55 - LD [I], V{:x}", x);
 
-                for n in 0..(x as usize + 1) {
-                    self.memory[self.index + n as usize] = self.registers[n as usize];
+                let count = x as usize + 1;
+                let range = self.checked_range(self.index, count)?;
+                self.memory[range].copy_from_slice(&self.registers[..count]);
+                if self.quirks.load_store_increments_index {
+                    self.index += x as usize + 1;
                 }
 
                 PC::Next
             }
             // Fx65 - LD Vx, [I]
-            (0xF, x, 0x6, 0x5) => {
+            Instruction::LdVxI { x } => {
                 debug!("Fx65 - LD V{:x}, [I]", x);
 
-                for n in 0..(x as usize + 1) {
-                    self.registers[n] = self.memory[self.index as usize + n];
+                let count = x as usize + 1;
+                let range = self.checked_range(self.index, count)?;
+                self.registers[..count].copy_from_slice(&self.memory[range]);
+                if self.quirks.load_store_increments_index {
+                    self.index += x as usize + 1;
                 }
 
                 PC::Next
             }
-            _ => panic!("Unknown instruction: {:x}{:x}{:x}{:x}", o1, o2, o3, o4),
+            // Fx75 - LD R, Vx
+            Instruction::LdRVx { x } => {
+                debug!("Fx75 - LD R, V{:x}", x);
+
+                let n = (x as usize + 1).min(self.rpl_flags.len());
+                self.rpl_flags[..n].copy_from_slice(&self.registers[..n]);
+
+                PC::Next
+            }
+            // Fx85 - LD Vx, R
+            Instruction::LdVxR { x } => {
+                debug!("Fx85 - LD V{:x}, R", x);
+
+                let n = (x as usize + 1).min(self.rpl_flags.len());
+                self.registers[..n].copy_from_slice(&self.rpl_flags[..n]);
+
+                PC::Next
+            }
+            Instruction::Unknown(_) => match self.unknown_opcode_policy {
+                UnknownOpcodePolicy::Panic => panic!("Unknown instruction: {:04x}", instruction),
+                UnknownOpcodePolicy::Nop => {
+                    debug!("Skipping unknown instruction {:04x}", instruction);
+                    PC::Next
+                }
+                UnknownOpcodePolicy::Halt => return Err(Chip8Error::UnknownOpcode(instruction)),
+            },
         };
 
         match pc_change {
             PC::Next => self.pc += 2,
-            PC::Skip => self.pc += 4,
+            // XO-CHIP skips jump over the whole of a double-width `F000 nnnn`
+            PC::Skip if matches!(self.word_at(self.pc + 2), Ok(0xF000)) => self.pc += 6,
+            PC::Skip | PC::NextLong => self.pc += 4,
             PC::Jump(v) => self.pc = v,
         }
+        if let Some(counts) = &mut self.opcode_profile {
+            counts[decoded.kind()] += 1;
+        }
+
+        Ok(())
+    }
+
+    fn word_at(&self, addr: usize) -> Result<u16, Chip8Error> {
+        let range = self.checked_range(addr, 2)?;
+        Ok(u16::from_be_bytes([
+            self.memory[range.start],
+            self.memory[range.start + 1],
+        ]))
+    }
+
+    /// The addresses of `len` bytes from `start`, or an error naming the first address past the
+    /// end of memory.
+    fn checked_range(&self, start: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if start + len <= self.memory.len() {
+            Ok(start..start + len)
+        } else {
+            Err(Chip8Error::OutOfBounds(start.max(self.memory.len())))
+        }
+    }
+
+    /// Copies `len` sprite bytes starting at `I` into `buffer`, so drawing doesn't allocate.
+    /// Sprites that run past the end of memory wrap around to address 0, as the 12-bit address
+    /// bus would, rather than reading out of bounds.
+    fn sprite_bytes<'a>(
+        &self,
+        buffer: &'a mut [u8; Self::MAX_SPRITE_BYTES],
+        len: usize,
+    ) -> &'a [u8] {
+        let bytes = &mut buffer[..len];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.memory[(self.index + i) % self.memory.len()];
+        }
+        bytes
+    }
+
+    /// The opcode at `pc`, i.e. the instruction the next `cycle` will execute, or 0 if `pc` is
+    /// past the end of memory.
+    pub fn opcode(&self) -> u16 {
+        self.word_at(self.pc).unwrap_or(0)
+    }
+
+    /// Executes the instruction at `pc`, returning its opcode. On error the machine is left as it
+    /// was before the instruction, with `pc` still pointing at it.
+    pub fn cycle(&mut self) -> Result<u16, Chip8Error> {
+        let opcode = self.word_at(self.pc)?;
+        self.process_instruction(opcode)?;
+        self.cycles += 1;
+        Ok(opcode)
+    }
+
+    /// The number of instructions executed since power-on or the last reset. Failed
+    /// instructions aren't counted.
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
     }
 
-    pub fn cycle(&mut self) {
-        let opcode = ((self.memory[self.pc] as u16) << 8) | self.memory[self.pc + 1] as u16;
-        self.process_instruction(opcode);
+    /// Whether a `Dxyn` has drawn this frame under the display-wait quirk, in which case the CPU
+    /// should execute nothing more until the next `tick_timers`.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
 
+    /// Ends a 60Hz frame, counting the timers down and ending any display wait.
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -504,7 +1090,71 @@ impl Chip8 {
         }
     }
 
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    /// Up to `len` bytes of memory starting at `start`, truncated at the end of memory.
+    pub fn memory_slice(&self, start: usize, len: usize) -> &[u8] {
+        let end = start.saturating_add(len).min(self.memory.len());
+        &self.memory[start.min(end)..end]
+    }
+
+    /// SUPER-CHIP RPL user flags, written by `Fx75` and read back by `Fx85`.
+    pub fn rpl_flags(&self) -> &[u8; 8] {
+        &self.rpl_flags
+    }
+
+    /// Loads previously persisted RPL user flags. Missing trailing bytes are zero-filled and any
+    /// extra bytes are ignored.
+    pub fn set_rpl_flags(&mut self, flags: &[u8]) {
+        let n = flags.len().min(self.rpl_flags.len());
+        self.rpl_flags = [0; 8];
+        self.rpl_flags[..n].copy_from_slice(&flags[..n]);
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The XO-CHIP audio pattern to play while the sound timer is running, if one was uploaded.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.pattern_buffer.as_ref()
+    }
+
+    /// Rate, in bits per second, at which the audio pattern is played back.
+    pub fn pattern_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - Self::DEFAULT_PITCH as f32) / 48.0)
+    }
+
     pub fn press_key(&mut self, idx: usize) {
+        if self.keypad[idx] == 0 {
+            self.key_presses |= 1 << idx;
+        }
         self.keypad[idx] = 1;
     }
 
@@ -512,15 +1162,1192 @@ impl Chip8 {
         self.keypad[idx] = 0;
     }
 
-    pub fn get_video(&self) -> &[u32; Display::SIZE] {
+    pub fn get_video(&self) -> &[u32] {
         self.display.view()
     }
 
+    /// Stable hash of [`Chip8::get_video`], as printed by headless runs.
+    pub fn video_hash(&self) -> u64 {
+        self.display.hash()
+    }
+
+    /// See [`Display::as_bitmask`].
+    pub fn video_bitmask(&self) -> [u64; Display::VIDEO_HEIGHT] {
+        self.display.as_bitmask()
+    }
+
+    /// Current display `(width, height)`, which changes when switching to SUPER-CHIP hi-res.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.display.width(), self.display.height())
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.display.is_dirty()
     }
 
+    /// See [`Display::render_rgba`].
+    pub fn render_rgba(&self, scale: usize, palette: &[[u8; 4]; 4]) -> Vec<u8> {
+        self.display.render_rgba(scale, palette)
+    }
+
+    /// See [`Display::render_ascii`].
+    pub fn render_ascii(&self) -> String {
+        self.display.render_ascii()
+    }
+
+    /// See [`Display::changed_pixels`].
+    pub fn changed_pixels(&self) -> Option<&[usize]> {
+        self.display.changed_pixels()
+    }
+
     pub fn set_clean(&mut self) {
         self.display.set_clean()
     }
+
+    pub fn force_redraw(&mut self) {
+        self.display.force_redraw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DrawMode;
+
+    #[test]
+    fn from_bytes_loads_at_start_address() {
+        let chip8 = Chip8::from_bytes(&[0x12, 0x34, 0x56]).unwrap();
+
+        assert_eq!(chip8.pc, Chip8::START_ADDRESS);
+        assert_eq!(
+            chip8.memory[Chip8::START_ADDRESS..Chip8::START_ADDRESS + 4],
+            [0x12, 0x34, 0x56, 0x00]
+        );
+        assert_eq!(
+            chip8.memory[Chip8::FONTSET_START_ADDRESS..Chip8::FONTSET_START_ADDRESS + 80],
+            Chip8::FONTSET
+        );
+    }
+
+    /// Writes `opcode` at `pc` and executes it.
+    fn run(chip8: &mut Chip8, opcode: u16) {
+        let pc = chip8.pc;
+        chip8.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+        chip8.cycle().unwrap();
+    }
+
+    #[test]
+    fn cls_clears_the_display() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.index = Chip8::FONTSET_START_ADDRESS;
+        run(&mut chip8, 0xD005);
+        assert!(chip8.get_video().contains(&1));
+
+        run(&mut chip8, 0x00E0);
+        assert!(!chip8.get_video().contains(&1));
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn jp_call_and_ret_move_pc() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+
+        run(&mut chip8, 0x1234);
+        assert_eq!(chip8.pc, 0x234);
+
+        run(&mut chip8, 0x2ABC);
+        assert_eq!((chip8.pc, chip8.sp), (0xABC, 1));
+
+        run(&mut chip8, 0x00EE);
+        assert_eq!((chip8.pc, chip8.sp), (0x236, 0));
+    }
+
+    #[test]
+    fn skips_compare_vx_with_bytes_and_registers() {
+        // (opcode, whether it skips) with V1 = 0x2A, V2 = 0x2A and V3 = 0x07
+        let cases = [
+            (0x312A, true),
+            (0x3107, false),
+            (0x412A, false),
+            (0x4107, true),
+            (0x5120, true),
+            (0x5130, false),
+            (0x9120, false),
+            (0x9130, true),
+        ];
+
+        for (opcode, skips) in cases {
+            let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+            chip8.registers[1..4].copy_from_slice(&[0x2A, 0x2A, 0x07]);
+
+            run(&mut chip8, opcode);
+            let expected = if skips { 0x204 } else { 0x202 };
+            assert_eq!(chip8.pc, expected, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn add_byte_wraps_without_touching_vf() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+
+        run(&mut chip8, 0x6AF0);
+        assert_eq!(chip8.registers[0xA], 0xF0);
+        run(&mut chip8, 0x7A20);
+        assert_eq!(chip8.registers[0xA], 0x10);
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn register_moves_and_logic() {
+        // (opcode, V1 afterwards) with V1 = 0b1100 and V2 = 0b1010
+        let cases = [
+            (0x8120, 0b1010),
+            (0x8121, 0b1110),
+            (0x8122, 0b1000),
+            (0x8123, 0b0110),
+        ];
+
+        for (opcode, result) in cases {
+            let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+            chip8.registers[1] = 0b1100;
+            chip8.registers[2] = 0b1010;
+
+            run(&mut chip8, opcode);
+            assert_eq!(chip8.registers[1], result, "{:04X}", opcode);
+            assert_eq!(chip8.registers[2], 0b1010, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn arithmetic_sets_carry_and_not_borrow_in_vf() {
+        // (opcode, V1, V2, V1 afterwards, VF afterwards)
+        let cases = [
+            (0x8124, 0x10, 0x20, 0x30, 0),
+            (0x8124, 0xF0, 0x20, 0x10, 1),
+            (0x8124, 0xFF, 0x01, 0x00, 1),
+            (0x8125, 0x30, 0x10, 0x20, 1),
+            (0x8125, 0x10, 0x30, 0xE0, 0),
+            (0x8125, 0x10, 0x10, 0x00, 1),
+            (0x8127, 0x10, 0x30, 0x20, 1),
+            (0x8127, 0x30, 0x10, 0xE0, 0),
+            (0x8127, 0x10, 0x10, 0x00, 1),
+        ];
+
+        for (opcode, v1, v2, result, flag) in cases {
+            let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+            chip8.registers[1] = v1;
+            chip8.registers[2] = v2;
+            chip8.registers[0xF] = 0x55;
+
+            run(&mut chip8, opcode);
+            assert_eq!(
+                (chip8.registers[1], chip8.registers[0xF]),
+                (result, flag),
+                "{:04X} with V1={:#04x} V2={:#04x}",
+                opcode,
+                v1,
+                v2
+            );
+        }
+    }
+
+    #[test]
+    fn draw_xors_sprites_and_flags_collisions() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[0] = 8;
+        chip8.registers[1] = 4;
+
+        // LD F, V0 points at the "8" glyph, 0xF0 0x90 0xF0 0x90 0xF0
+        run(&mut chip8, 0xF029);
+        run(&mut chip8, 0xD011);
+        let row = |chip8: &Chip8| chip8.get_video()[4 * 64 + 8..4 * 64 + 16].to_vec();
+        assert_eq!(row(&chip8), [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(chip8.registers[0xF], 0);
+
+        run(&mut chip8, 0xD011);
+        assert_eq!(row(&chip8), [0; 8]);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn key_skips_follow_the_keypad() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[4] = 0xB;
+
+        run(&mut chip8, 0xE49E);
+        assert_eq!(chip8.pc, 0x202);
+        run(&mut chip8, 0xE4A1);
+        assert_eq!(chip8.pc, 0x206);
+
+        chip8.press_key(0xB);
+        run(&mut chip8, 0xE49E);
+        assert_eq!(chip8.pc, 0x20A);
+        run(&mut chip8, 0xE4A1);
+        assert_eq!(chip8.pc, 0x20C);
+    }
+
+    #[test]
+    fn timers_load_and_read_back() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[2] = 30;
+
+        run(&mut chip8, 0xF215);
+        run(&mut chip8, 0xF218);
+        assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (30, 30));
+        assert!(chip8.is_beeping());
+
+        chip8.tick_timers();
+        run(&mut chip8, 0xF307);
+        assert_eq!(chip8.registers[3], 29);
+    }
+
+    #[test]
+    fn index_loads_and_adds() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[5] = 0x10;
+
+        run(&mut chip8, 0xA123);
+        assert_eq!(chip8.index, 0x123);
+        run(&mut chip8, 0xF51E);
+        assert_eq!(chip8.index, 0x133);
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn bcd_stores_three_digits_at_index() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[6] = 254;
+        chip8.index = 0x300;
+
+        run(&mut chip8, 0xF633);
+        assert_eq!(chip8.memory_slice(0x300, 3), [2, 5, 4]);
+        assert_eq!(chip8.index, 0x300);
+    }
+
+    #[test]
+    fn registers_store_and_load_through_index() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.index = 0x300;
+
+        run(&mut chip8, 0xF255);
+        assert_eq!(chip8.memory_slice(0x300, 4), [1, 2, 3, 0]);
+
+        chip8.registers = [0; 16];
+        run(&mut chip8, 0xF165);
+        assert_eq!(chip8.registers[..4], [1, 2, 0, 0]);
+    }
+
+    #[test]
+    fn reset_keeps_rom_and_fontset() {
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x2A, 0xA2, 0x34]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        chip8.press_key(0x3);
+
+        chip8.reset();
+
+        assert_eq!(chip8.pc, Chip8::START_ADDRESS);
+        assert_eq!(chip8.registers, [0; 16]);
+        assert_eq!(chip8.index, 0);
+        assert_eq!(chip8.keypad, [0; 16]);
+        assert_eq!(
+            chip8.memory[Chip8::START_ADDRESS..Chip8::START_ADDRESS + 4],
+            [0x60, 0x2A, 0xA2, 0x34]
+        );
+        assert_eq!(
+            chip8.memory[Chip8::FONTSET_START_ADDRESS..Chip8::FONTSET_START_ADDRESS + 80],
+            Chip8::FONTSET
+        );
+    }
+
+    #[test]
+    fn clones_run_identically() {
+        // RND V0; RND V1; LD I, 0x050; DRW V0, V1, 5; JP 0x200
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xA0, 0x50, 0xD0, 0x15, 0x12, 0x00];
+        let mut original = Chip8::from_bytes(&rom).unwrap().with_seed(0xACE1);
+        for _ in 0..7 {
+            original.cycle().unwrap();
+        }
+
+        let mut copy = original.clone();
+        // Whether the display still needs drawing doesn't count
+        copy.set_clean();
+        assert_eq!(copy, original);
+
+        for _ in 0..50 {
+            original.cycle().unwrap();
+            copy.cycle().unwrap();
+        }
+        assert_eq!(copy, original);
+
+        copy.cycle().unwrap();
+        assert_ne!(copy, original);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_state_round_trips() {
+        // RND V0; RND V1; LD I, 0x050; DRW V0, V1, 5; JP 0x200
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xA0, 0x50, 0xD0, 0x15, 0x12, 0x00];
+        let mut original = Chip8::from_bytes(&rom).unwrap();
+        for _ in 0..23 {
+            original.cycle().unwrap();
+        }
+
+        let state = original.save_state();
+        let mut restored = Chip8::from_bytes(&[]).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+
+        for _ in 0..50 {
+            original.cycle().unwrap();
+            restored.cycle().unwrap();
+        }
+        assert_eq!(restored.save_state(), original.save_state());
+        assert_eq!(restored.get_video(), original.get_video());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_state_rejects_garbage() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+
+        assert!(matches!(
+            chip8.load_state(&[1, 2, 3]),
+            Err(Chip8Error::InvalidState(_))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_state_rejects_impossible_values() {
+        // CALL 0x202; JP 0x202
+        let mut chip8 = Chip8::from_bytes(&[0x22, 0x02, 0x12, 0x02]).unwrap();
+        chip8.cycle().unwrap();
+        let before = chip8.save_state();
+
+        let mut corrupt = Chip8::from_bytes(&[]).unwrap();
+        corrupt.sp = 17;
+        assert!(matches!(
+            chip8.load_state(&corrupt.save_state()),
+            Err(Chip8Error::CorruptState(_))
+        ));
+
+        // The display is serialised as the length of its pixels followed by each pixel, so the
+        // first pixel comes 8 bytes into it
+        let mut state = before.clone();
+        let display = bincode::serialize(&chip8.display).unwrap();
+        let offset = state
+            .windows(display.len())
+            .position(|window| window == display)
+            .unwrap();
+        state[offset + 8] = 4;
+        assert!(matches!(
+            chip8.load_state(&state),
+            Err(Chip8Error::CorruptState(_))
+        ));
+
+        corrupt = Chip8::from_bytes(&[]).unwrap();
+        corrupt.key_wait = KeyWait::Release(16);
+        assert!(chip8.load_state(&corrupt.save_state()).is_err());
+
+        // A rejected state leaves the machine as it was
+        assert_eq!(chip8.save_state(), before);
+        chip8.cycle().unwrap();
+    }
+
+    #[test]
+    fn memory_slice_is_truncated_at_end_of_memory() {
+        let chip8 = Chip8::from_bytes(&[0xAB, 0xCD]).unwrap();
+
+        assert_eq!(chip8.memory_slice(Chip8::START_ADDRESS, 2), [0xAB, 0xCD]);
+        assert_eq!(chip8.memory_slice(Chip8::MEMORY_SIZE - 1, 4).len(), 1);
+        assert!(chip8.memory_slice(Chip8::MEMORY_SIZE + 10, 4).is_empty());
+    }
+
+    #[test]
+    fn high_and_low_switch_resolution() {
+        let mut chip8 = Chip8::from_bytes(&[0x00, 0xFF, 0x00, 0xFE]).unwrap();
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.resolution(), (128, 64));
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.resolution(), (64, 32));
+    }
+
+    #[test]
+    fn scroll_instructions_move_display() {
+        // HIGH; LD I, 0x050 ("0" glyph); DRW V0, V0, 1; SCD 2; SCR
+        let rom = [0x00, 0xFF, 0xA0, 0x50, 0xD0, 0x01, 0x00, 0xC2, 0x00, 0xFB];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        for _ in 0..5 {
+            chip8.cycle().unwrap();
+        }
+
+        let row = &chip8.get_video()[2 * 128..3 * 128];
+        assert_eq!(row[..8], [0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(chip8.get_video().iter().sum::<u32>(), 4);
+    }
+
+    #[test]
+    fn draws_16x16_sprite() {
+        // HIGH; LD I, 0x20A; DRW V0, V0, 0; DRW V0, V0, 0; sprite data follows
+        let mut rom = vec![0x00, 0xFF, 0xA2, 0x0A, 0xD0, 0x00, 0xD0, 0x00, 0x00, 0x00];
+        rom.extend([0xFF; 32]);
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+
+        let video = chip8.get_video();
+        for y in 0..16 {
+            assert_eq!(
+                video[y * 128..y * 128 + 17],
+                [[1; 16].as_slice(), &[0]].concat()
+            );
+        }
+        assert_eq!(video.iter().sum::<u32>(), 256);
+        assert_eq!(chip8.registers[0xF], 0);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_video().iter().sum::<u32>(), 0);
+        assert_eq!(chip8.registers[0xF], 16);
+    }
+
+    #[test]
+    fn lores_collision_is_a_flag() {
+        // LD I, 0x050 ("0" glyph); DRW V0, V0, 5; DRW V0, V0, 5
+        let mut chip8 = Chip8::from_bytes(&[0xA0, 0x50, 0xD0, 0x05, 0xD0, 0x05]).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn big_font_points_at_10_byte_glyphs() {
+        // LD V3, 0x0B; LD HF, V3; LD F, V3
+        let mut chip8 = Chip8::from_bytes(&[0x63, 0x0B, 0xF3, 0x30, 0xF3, 0x29]).unwrap();
+        chip8.cycle().unwrap();
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.index, Chip8::BIG_FONTSET_START_ADDRESS + 110);
+        assert_eq!(
+            chip8.memory_slice(chip8.index, 10),
+            &Chip8::BIG_FONTSET[110..120]
+        );
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.index, Chip8::FONTSET_START_ADDRESS + 55);
+        assert_eq!(chip8.memory_slice(chip8.index, 5), &Chip8::FONTSET[55..60]);
+    }
+
+    #[test]
+    fn rpl_flags_survive_reset() {
+        // LD V0, 0x11; LD V1, 0x22; LD V2, 0x33; LD R, V1; LD V2, R
+        let rom = [0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xF1, 0x75, 0xF2, 0x85];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.rpl_flags()[..3], [0x11, 0x22, 0x00]);
+
+        chip8.reset();
+        chip8.pc = Chip8::START_ADDRESS + 8;
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[..4], [0x11, 0x22, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn set_rpl_flags_zero_fills_short_input() {
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap();
+        chip8.set_rpl_flags(&[1; 8]);
+
+        chip8.set_rpl_flags(&[7, 8]);
+
+        assert_eq!(chip8.rpl_flags(), &[7, 8, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn uploads_audio_pattern_and_pitch() {
+        // LD I, 0x208; AUDIO; LD V1, 0x70; PITCH V1; pattern data follows
+        let mut rom = vec![0xA2, 0x08, 0xF0, 0x02, 0x61, 0x70, 0xF1, 0x3A];
+        rom.extend(0..16);
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        assert_eq!(chip8.audio_pattern(), None);
+        assert_eq!(chip8.pattern_playback_rate(), 4000.0);
+
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+
+        let expected: Vec<u8> = (0..16).collect();
+        assert_eq!(chip8.audio_pattern().unwrap(), expected.as_slice());
+        assert_eq!(chip8.pattern_playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn loads_16_bit_index() {
+        // LD I, LONG 0xBEEF; LD V0, 0x01
+        let mut chip8 = Chip8::from_bytes(&[0xF0, 0x00, 0xBE, 0xEF, 0x60, 0x01]).unwrap();
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.index, 0xBEEF);
+        assert_eq!(chip8.pc, Chip8::START_ADDRESS + 4);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0], 0x01);
+    }
+
+    #[test]
+    fn skip_steps_over_long_load() {
+        // SE V0, 0x00; LD I, LONG 0xBEEF; LD V1, 0x01
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0xBE, 0xEF, 0x61, 0x01];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, Chip8::START_ADDRESS + 6);
+    }
+
+    #[test]
+    fn shifts_vx_in_place_by_default() {
+        // LD V1, 0x81; LD V2, 0x02; SHR V1, V2; LD V1, 0x81; SHL V1, V2
+        let rom = [0x61, 0x81, 0x62, 0x02, 0x81, 0x26, 0x61, 0x81, 0x81, 0x2E];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x1], 0x40);
+        assert_eq!(chip8.registers[0xF], 1);
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x1], 0x02);
+        assert_eq!(chip8.registers[0xF], 1);
+        assert_eq!(chip8.registers[0x2], 0x02);
+    }
+
+    #[test]
+    fn shift_quirk_shifts_vy_into_vx() {
+        // LD V2, 0x81; SHR V1, V2; LD V2, 0x40; SHL V1, V2
+        let rom = [0x62, 0x81, 0x81, 0x26, 0x62, 0x40, 0x81, 0x2E];
+        let quirks = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x1], 0x40);
+        assert_eq!(chip8.registers[0x2], 0x81);
+        assert_eq!(chip8.registers[0xF], 1);
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0x1], 0x80);
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn shifted_out_bit_wins_when_shifting_vf() {
+        // LD VF, 0x03; SHR VF
+        let mut chip8 = Chip8::from_bytes(&[0x6F, 0x03, 0x8F, 0x06]).unwrap();
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn load_store_quirk_increments_index() {
+        // LD I, 0x208; LD V1, [I]; LD V1, [I]; LD [I], V0; data
+        let rom = [
+            0xA2, 0x08, 0xF1, 0x65, 0xF1, 0x65, 0xF0, 0x55, 0x11, 0x22, 0x33, 0x44,
+        ];
+        let quirks = Quirks {
+            load_store_increments_index: true,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[..2], [0x11, 0x22]);
+        assert_eq!(chip8.index, 0x20A);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[..2], [0x33, 0x44]);
+        assert_eq!(chip8.index, 0x20C);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.memory[0x20C], 0x33);
+        assert_eq!(chip8.index, 0x20D);
+    }
+
+    #[test]
+    fn index_overflow_quirk_sets_vf_and_wraps_index() {
+        let quirks = Quirks {
+            index_overflow_sets_vf: true,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&[]).unwrap().with_quirks(quirks);
+        chip8.registers[5] = 0x10;
+
+        run(&mut chip8, 0xAFF8);
+        run(&mut chip8, 0xF51E);
+        assert_eq!(chip8.index, 0x008);
+        assert_eq!(chip8.registers[0xF], 1);
+
+        run(&mut chip8, 0xF51E);
+        assert_eq!(chip8.index, 0x018);
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn load_leaves_index_unchanged_by_default() {
+        // LD I, 0x206; LD V1, [I]; data
+        let mut chip8 =
+            Chip8::from_bytes(&[0xA2, 0x06, 0xF1, 0x65, 0x00, 0x00, 0x11, 0x22]).unwrap();
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[..2], [0x11, 0x22]);
+        assert_eq!(chip8.index, 0x206);
+    }
+
+    #[test]
+    fn clip_quirk_stops_sprites_wrapping() {
+        // LD V0, 0x3F; LD I, 0x208; DRW V0, V1, 1; data
+        let rom = [0x60, 0x3F, 0xA2, 0x08, 0xD0, 0x11, 0x00, 0x00, 0xC0];
+        let quirks = Quirks {
+            display_wraps: false,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.get_video()[..2], [0, 0]);
+        assert_eq!(chip8.get_video()[63], 1);
+    }
+
+    #[test]
+    fn vf_reset_quirk_clears_vf_after_logic() {
+        let quirks = Quirks {
+            vf_reset_on_logic: true,
+            ..Quirks::default()
+        };
+
+        for op in [0x1, 0x2, 0x3] {
+            // LD VF, 0x05; LD V1, 0x0C; OR/AND/XOR V1, V1
+            let rom = [0x6F, 0x05, 0x61, 0x0C, 0x81, 0x10 | op];
+            let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+
+            for _ in 0..3 {
+                chip8.cycle().unwrap();
+            }
+            assert_eq!(chip8.registers[0xF], 0, "8xy{}", op);
+        }
+
+        // Without the quirk VF is left alone
+        let mut chip8 = Chip8::from_bytes(&[0x6F, 0x05, 0x61, 0x0C, 0x81, 0x11]).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[0xF], 0x05);
+    }
+
+    #[test]
+    fn display_wait_quirk_holds_draws_until_the_next_frame() {
+        // DRW V0, V0, 1; DRW V0, V0, 1
+        let rom = [0xD0, 0x01, 0xD0, 0x01];
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        chip8.cycle().unwrap();
+        assert!(!chip8.is_waiting_for_vblank());
+
+        let quirks = Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+        chip8.cycle().unwrap();
+        assert!(chip8.is_waiting_for_vblank());
+        chip8.tick_timers();
+        assert!(!chip8.is_waiting_for_vblank());
+    }
+
+    #[test]
+    fn jump_with_offset_target_depends_on_quirk() {
+        // LD V0, 0x04; LD V3, 0x10; JP V0, 0x300
+        let rom = [0x60, 0x04, 0x63, 0x10, 0xB3, 0x00];
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        assert_eq!(chip8.quirks(), Quirks::default());
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc, 0x304);
+
+        let quirks = Quirks {
+            jump_with_offset_uses_vx: true,
+            ..Quirks::default()
+        };
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_quirks(quirks);
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc, 0x310);
+    }
+
+    #[test]
+    fn same_seed_gives_same_random_numbers() {
+        // RND V0, 0xFF; RND V1, 0xFF
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF];
+        let run = |seed| {
+            let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_seed(seed);
+            chip8.cycle().unwrap();
+            chip8.cycle().unwrap();
+            chip8.registers[..2].to_vec()
+        };
+
+        assert_eq!(run(0xACE1), run(0xACE1));
+        assert_ne!(run(0xACE1), run(0x1234));
+    }
+
+    #[test]
+    fn draws_random_numbers_from_a_swapped_in_generator() {
+        #[derive(Clone, Debug)]
+        struct Counter(u8);
+
+        impl Rng for Counter {
+            fn next_byte(&mut self) -> u8 {
+                self.0 += 1;
+                self.0
+            }
+        }
+
+        // RND V0, 0xFF; RND V1, 0xFF; RND V2, 0x0E
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0x0E];
+        let mut chip8 = Chip8::from_bytes(&rom)
+            .unwrap()
+            .with_rng(Box::new(Counter(0)));
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[..3], [1, 2, 3 & 0x0E]);
+    }
+
+    #[test]
+    fn random_numbers_are_just_the_mask_without_randomness() {
+        // RND V0, 0x3C; RND V1, 0xFF; RND V2, 0x00
+        let rom = [0xC0, 0x3C, 0xC1, 0xFF, 0xC2, 0x00];
+        for seed in [0xACE1, 0x1234] {
+            let mut chip8 = Chip8::from_bytes(&rom)
+                .unwrap()
+                .with_seed(seed)
+                .without_randomness();
+            for _ in 0..3 {
+                chip8.cycle().unwrap();
+            }
+            assert_eq!(chip8.registers[..3], [0x3C, 0xFF, 0x00]);
+        }
+    }
+
+    #[test]
+    fn cycle_returns_the_executed_opcode() {
+        // LD V0, 0x2A; JP 0x200
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x2A, 0x12, 0x00]).unwrap();
+
+        assert_eq!(chip8.cycle().unwrap(), 0x602A);
+        assert_eq!(chip8.cycle().unwrap(), 0x1200);
+        assert_eq!(chip8.cycle().unwrap(), 0x602A);
+    }
+
+    #[test]
+    fn profiles_instructions_by_kind() {
+        // LD V0, 0x01; ADD V0, 0x01; JP 0x202
+        let rom = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.opcode_profile().is_none());
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_opcode_profile();
+        for _ in 0..7 {
+            chip8.cycle().unwrap();
+        }
+        let counts = chip8.opcode_profile().unwrap();
+        let count = |opcode| counts[decode(opcode).kind()];
+        assert_eq!((count(0x6001), count(0x7001), count(0x1202)), (1, 3, 3));
+        assert_eq!(counts.iter().sum::<u64>(), 7);
+
+        // Failed instructions aren't counted: RET with an empty stack, and an unknown opcode
+        for rom in [[0x00, 0xEE], [0xFF, 0xFF]] {
+            let mut chip8 = Chip8::from_bytes(&rom).unwrap().with_opcode_profile();
+            assert!(chip8.cycle().is_err());
+            assert_eq!(chip8.opcode_profile().unwrap().iter().sum::<u64>(), 0);
+        }
+    }
+
+    #[test]
+    fn from_bytes_rejects_oversized_rom() {
+        let max = Chip8::MEMORY_SIZE - Chip8::START_ADDRESS;
+
+        assert!(Chip8::from_bytes(&vec![0; max]).is_ok());
+        assert!(matches!(
+            Chip8::from_bytes(&vec![0; max + 1]),
+            Err(Chip8Error::RomTooLarge { size, max: m }) if size == max + 1 && m == max
+        ));
+    }
+
+    #[test]
+    fn read_rom_loads_whole_file_and_rejects_oversized_ones() {
+        let max = Chip8::MEMORY_SIZE - Chip8::START_ADDRESS;
+        let dir = std::env::temp_dir();
+
+        let path = dir.join(format!("chip8-full-{}.ch8", std::process::id()));
+        let mut rom = vec![0; max];
+        rom[max - 1] = 0xAB;
+        fs::write(&path, &rom).unwrap();
+        let chip8 = Chip8::read_rom(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(chip8.unwrap().memory[Chip8::MEMORY_SIZE - 1], 0xAB);
+
+        let path = dir.join(format!("chip8-oversized-{}.ch8", std::process::id()));
+        fs::write(&path, vec![0; max + 1]).unwrap();
+        let chip8 = Chip8::read_rom(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(chip8, Err(Chip8Error::RomTooLarge { .. })));
+    }
+
+    #[test]
+    fn sprite_reads_wrap_around_the_end_of_memory() {
+        // LD V0, 0xFF; LD I, 0xFFF; LD [I], V0; LD V0, 0x00; DRW V0, V0, 2
+        let rom = [0x60, 0xFF, 0xAF, 0xFF, 0xF0, 0x55, 0x60, 0x00, 0xD0, 0x02];
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+
+        for _ in 0..5 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.get_video()[..8], [1; 8]);
+        // The second row comes from address 0, which is always blank
+        assert_eq!(chip8.get_video()[64..72], [0; 8]);
+    }
+
+    #[test]
+    fn deep_recursion_overflows_the_stack() {
+        // CALL 0x200, i.e. the ROM calls itself forever
+        let mut chip8 = Chip8::from_bytes(&[0x22, 0x00]).unwrap();
+
+        for _ in 0..16 {
+            chip8.cycle().unwrap();
+        }
+        assert!(matches!(chip8.cycle(), Err(Chip8Error::StackOverflow)));
+        assert_eq!(chip8.sp(), 16);
+    }
+
+    #[test]
+    fn return_with_empty_stack_underflows() {
+        // RET
+        let mut chip8 = Chip8::from_bytes(&[0x00, 0xEE]).unwrap();
+
+        assert!(matches!(chip8.cycle(), Err(Chip8Error::StackUnderflow)));
+        assert_eq!(chip8.pc(), Chip8::START_ADDRESS);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_errors() {
+        let mut chip8 = Chip8::from_bytes(&[0x5A, 0xB1]).unwrap();
+
+        assert!(matches!(
+            chip8.cycle(),
+            Err(Chip8Error::UnknownOpcode(0x5AB1))
+        ));
+        assert_eq!(chip8.pc(), Chip8::START_ADDRESS);
+    }
+
+    #[test]
+    fn memory_accesses_past_the_end_are_errors() {
+        // LD I, 0xFFE; LD [I], V2
+        let mut chip8 = Chip8::from_bytes(&[0xAF, 0xFE, 0xF2, 0x55]).unwrap();
+        chip8.cycle().unwrap();
+        assert!(matches!(
+            chip8.cycle(),
+            Err(Chip8Error::OutOfBounds(0x1000))
+        ));
+        // Nothing was written before the error
+        assert_eq!(chip8.memory_slice(0xFFE, 2), [0, 0]);
+
+        // JP 0xFFF, leaving only half an instruction to fetch
+        let mut chip8 = Chip8::from_bytes(&[0x1F, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        assert!(matches!(
+            chip8.cycle(),
+            Err(Chip8Error::OutOfBounds(0x1000))
+        ));
+    }
+
+    #[test]
+    fn nop_policy_skips_unknown_opcodes() {
+        let mut chip8 = Chip8::from_bytes(&[0x5A, 0xB1])
+            .unwrap()
+            .with_unknown_opcode_policy(UnknownOpcodePolicy::Nop);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc(), Chip8::START_ADDRESS + 2);
+    }
+
+    #[test]
+    fn halt_policy_returns_an_error() {
+        let mut chip8 = Chip8::from_bytes(&[0x5A, 0xB1])
+            .unwrap()
+            .with_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+
+        assert!(matches!(
+            chip8.cycle(),
+            Err(Chip8Error::UnknownOpcode(0x5AB1))
+        ));
+        assert!(matches!(
+            chip8.cycle(),
+            Err(Chip8Error::UnknownOpcode(0x5AB1))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown instruction: 5ab1")]
+    fn panic_policy_panics() {
+        let mut chip8 = Chip8::from_bytes(&[0x5A, 0xB1])
+            .unwrap()
+            .with_unknown_opcode_policy(UnknownOpcodePolicy::Panic);
+
+        let _ = chip8.cycle();
+    }
+
+    #[test]
+    fn custom_fontset_is_used_by_fx29() {
+        let mut fontset = [0; 80];
+        fontset[5..10].copy_from_slice(&[0x20, 0x60, 0x20, 0x20, 0x70]);
+        // LD V0, 0x1; LD F, V0
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01, 0xF0, 0x29])
+            .unwrap()
+            .with_fontset(&fontset);
+
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(
+            chip8.memory_slice(chip8.index(), 5),
+            [0x20, 0x60, 0x20, 0x20, 0x70]
+        );
+    }
+
+    #[test]
+    fn counts_executed_instructions() {
+        // LD V0, 0x01; JP 0x200
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01, 0x12, 0x00, 0xFF, 0xFF]).unwrap();
+
+        for _ in 0..5 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.cycle_count(), 5);
+
+        chip8.reset();
+        assert_eq!(chip8.cycle_count(), 0);
+    }
+
+    #[test]
+    fn key_wait_ignores_held_keys() {
+        // LD V3, K
+        let mut chip8 = Chip8::from_bytes(&[0xF3, 0x0A]).unwrap();
+        chip8.press_key(0x5);
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc, 0x200);
+
+        chip8.lift_key(0x5);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!(chip8.registers[3], 0);
+    }
+
+    #[test]
+    fn key_wait_completes_on_release() {
+        // LD V3, K
+        let mut chip8 = Chip8::from_bytes(&[0xF3, 0x0A]).unwrap();
+        chip8.press_key(0x5);
+        chip8.cycle().unwrap();
+
+        chip8.lift_key(0x5);
+        chip8.press_key(0x7);
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc, 0x200);
+
+        chip8.lift_key(0x7);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, 0x202);
+        assert_eq!(chip8.registers[3], 0x7);
+    }
+
+    #[test]
+    fn key_wait_catches_a_tap_between_cycles() {
+        // LD V3, K
+        let mut chip8 = Chip8::from_bytes(&[0xF3, 0x0A]).unwrap();
+        chip8.cycle().unwrap();
+
+        chip8.press_key(0xA);
+        chip8.lift_key(0xA);
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc, 0x202);
+        assert_eq!(chip8.registers[3], 0xA);
+    }
+
+    #[test]
+    fn loads_at_alternate_start_address() {
+        // LD V0, 0x2A
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x2A])
+            .unwrap()
+            .with_start_address(0x600)
+            .unwrap();
+        assert_eq!(chip8.memory_slice(0x200, 2), [0, 0]);
+        assert_eq!((chip8.pc(), chip8.opcode()), (0x600, 0x602A));
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0], 0x2A);
+        chip8.reset();
+        assert_eq!(chip8.pc(), 0x600);
+    }
+
+    #[test]
+    fn rejects_start_addresses_the_rom_cant_go_at() {
+        let rom = Chip8::from_bytes(&[0xFF; 0x200]).unwrap();
+
+        for address in [0, 0x100, Chip8::MEMORY_SIZE] {
+            assert!(matches!(
+                rom.clone().with_start_address(address),
+                Err(Chip8Error::InvalidStartAddress(a)) if a == address
+            ));
+        }
+        assert!(rom.clone().with_start_address(0x600).is_ok());
+        assert!(matches!(
+            rom.with_start_address(0xF00),
+            Err(Chip8Error::RomTooLarge {
+                size: 0x200,
+                max: 0x100
+            })
+        ));
+    }
+
+    #[test]
+    fn xo_chip_memory_reaches_past_4k() {
+        // LD I, LONG 0x8000; LD V0, 0x2A; LD [I], V0
+        let rom = [0xF0, 0x00, 0x80, 0x00, 0x60, 0x2A, 0xF0, 0x55];
+
+        let mut chip8 =
+            Chip8::from_bytes_with_memory_size(&rom, Chip8::XO_CHIP_MEMORY_SIZE).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.memory_slice(0x8000, 1), [0x2A]);
+        assert_eq!(
+            chip8.memory_slice(Chip8::FONTSET_START_ADDRESS, 5),
+            &Chip8::FONTSET[..5]
+        );
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        for _ in 0..2 {
+            chip8.cycle().unwrap();
+        }
+        assert!(matches!(chip8.cycle(), Err(Chip8Error::OutOfBounds(_))));
+    }
+
+    #[test]
+    fn rejects_memory_without_room_for_a_rom() {
+        assert!(matches!(
+            Chip8::from_bytes_with_memory_size(&[], Chip8::START_ADDRESS),
+            Err(Chip8Error::InvalidStartAddress(_))
+        ));
+    }
+
+    #[test]
+    fn reload_replaces_the_rom_and_keeps_settings() {
+        let quirks = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        // LD V0, 0x01; LD V1, 0x02
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01, 0x61, 0x02])
+            .unwrap()
+            .with_quirks(quirks)
+            .with_start_address(0x300)
+            .unwrap();
+        chip8.cycle().unwrap();
+
+        // LD V0, 0x03
+        chip8.reload(&[0x60, 0x03]).unwrap();
+        assert_eq!(chip8.memory_slice(0x300, 4), [0x60, 0x03, 0x00, 0x00]);
+        assert_eq!((chip8.pc(), chip8.registers[0]), (0x300, 0));
+        assert_eq!(chip8.quirks(), quirks);
+
+        assert!(chip8.reload(&[0; 0xD01]).is_err());
+    }
+
+    proptest::proptest! {
+        /// Any opcode run against any machine state either executes or returns an error, never
+        /// panicking or reaching outside memory, the stack or the display. Run more cases with
+        /// e.g. `PROPTEST_CASES=100000 cargo test instructions_never_panic`.
+        #[test]
+        fn instructions_never_panic(
+            opcode: u16,
+            memory in proptest::collection::vec(proptest::num::u8::ANY, Chip8::MEMORY_SIZE),
+            registers: [u8; 16],
+            index in 0..=0xFFFFusize,
+            pc in 0..Chip8::MEMORY_SIZE,
+            stack: [u16; 16],
+            sp in 0..=16usize,
+            hires: bool,
+            quirks: (bool, bool, bool, bool, bool, bool, bool, bool),
+        ) {
+            let mut chip8 = Chip8::from_bytes(&[])
+                .unwrap()
+                .with_quirks(Quirks {
+                    shift_uses_vy: quirks.0,
+                    load_store_increments_index: quirks.1,
+                    jump_with_offset_uses_vx: quirks.2,
+                    display_wraps: quirks.3,
+                    vf_reset_on_logic: quirks.4,
+                    display_wait: quirks.5,
+                    index_overflow_sets_vf: quirks.6,
+                    draw_mode: if quirks.7 { DrawMode::Or } else { DrawMode::Xor },
+                })
+                .with_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+            chip8.memory = memory;
+            chip8.registers = registers;
+            chip8.index = index;
+            chip8.pc = pc;
+            chip8.stack = stack;
+            chip8.sp = sp;
+            chip8.display.set_hires(hires);
+
+            let _ = chip8.process_instruction(opcode);
+        }
+    }
 }
@@ -0,0 +1,405 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use chip8::Quirks;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use sdl2::pixels::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::Waveform;
+use crate::keymap::Layout;
+use crate::palette::Palette;
+use crate::{Args, Backend, Memory, OnUnknown};
+
+/// Settings read from a `--config` file and written by `--save-config`, named after their flags.
+/// Anything left out of a file keeps its command line value or default.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+    instructions_per_frame: Option<u32>,
+    turbo_factor: Option<u32>,
+    speed_cpu_only: Option<bool>,
+    #[serde(with = "value_enum")]
+    memory: Option<Memory>,
+    #[serde(with = "value_enum")]
+    backend: Option<Backend>,
+    #[serde(with = "value_enum")]
+    on_unknown: Option<OnUnknown>,
+    font: Option<PathBuf>,
+
+    #[serde(with = "value_enum")]
+    palette: Option<Palette>,
+    #[serde(with = "color")]
+    fg: Option<Color>,
+    #[serde(with = "color")]
+    bg: Option<Color>,
+    scale: Option<u32>,
+    stretch: Option<bool>,
+    fade: Option<bool>,
+    crt: Option<bool>,
+    grid: Option<bool>,
+    #[serde(with = "color")]
+    grid_color: Option<Color>,
+
+    keymap: Option<PathBuf>,
+    #[serde(with = "value_enum")]
+    layout: Option<Layout>,
+    controller_map: Option<PathBuf>,
+
+    mute: Option<bool>,
+    volume: Option<u8>,
+    #[serde(with = "value_enum")]
+    waveform: Option<Waveform>,
+    frequency: Option<u16>,
+
+    quirks: Option<Quirks>,
+}
+
+impl Config {
+    /// The settings `args` run with, with the `quirks` they resolved to.
+    pub(crate) fn from_args(args: &Args, quirks: Quirks) -> Self {
+        Self {
+            instructions_per_frame: Some(args.instructions_per_frame()),
+            turbo_factor: Some(args.turbo_factor),
+            speed_cpu_only: Some(args.speed_cpu_only),
+            memory: Some(args.memory),
+            backend: Some(args.backend),
+            on_unknown: Some(args.on_unknown),
+            font: args.font.clone(),
+            palette: Some(args.palette),
+            fg: args.fg,
+            bg: args.bg,
+            scale: args.scale,
+            stretch: Some(args.stretch),
+            fade: Some(args.fade),
+            crt: Some(args.crt),
+            grid: Some(args.grid),
+            grid_color: Some(args.grid_color),
+            keymap: args.keymap.clone(),
+            layout: Some(args.layout),
+            controller_map: args.controller_map.clone(),
+            mute: Some(args.mute),
+            volume: Some(args.volume),
+            waveform: Some(args.waveform),
+            frequency: Some(args.frequency),
+            quirks: Some(quirks),
+        }
+    }
+
+    fn paths_mut(&mut self) -> [&mut Option<PathBuf>; 3] {
+        [&mut self.font, &mut self.keymap, &mut self.controller_map]
+    }
+
+    /// Checks the ranges the command line enforces through its value parsers.
+    fn validate(&self) -> Result<(), String> {
+        check_range(
+            "instructions-per-frame",
+            self.instructions_per_frame,
+            1..=u32::MAX,
+        )?;
+        check_range("turbo-factor", self.turbo_factor, 1..=u32::MAX)?;
+        check_range("scale", self.scale, 1..=u32::MAX)?;
+        check_range("volume", self.volume.map(u32::from), 0..=100)?;
+        check_range("frequency", self.frequency.map(u32::from), 20..=20000)
+    }
+
+    /// Fills in the settings the command line left out of `args`, so a flag given there wins over
+    /// the file, and the file over the defaults. A file setting is also dropped when the command
+    /// line gives one that takes precedence over it, e.g. `--cycle-delay` over
+    /// `instructions-per-frame` or `--layout` over `keymap`. Flags the file turns on can't be
+    /// turned off again from the command line.
+    ///
+    /// Returns the file's quirks, for the quirk flags on the command line to be applied on top.
+    pub(crate) fn apply(self, args: &mut Args, matches: &ArgMatches) -> Quirks {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        merge(
+            &mut args.ipf,
+            self.instructions_per_frame.map(Some),
+            given("ipf") || given("cycle_delay"),
+        );
+        merge(
+            &mut args.turbo_factor,
+            self.turbo_factor,
+            given("turbo_factor"),
+        );
+        args.speed_cpu_only |= self.speed_cpu_only.unwrap_or(false);
+        merge(&mut args.memory, self.memory, given("memory"));
+        merge(&mut args.backend, self.backend, given("backend"));
+        merge(&mut args.on_unknown, self.on_unknown, given("on_unknown"));
+        merge(&mut args.font, self.font.map(Some), given("font"));
+
+        merge(&mut args.palette, self.palette, given("palette"));
+        merge(
+            &mut args.fg,
+            self.fg.map(Some),
+            given("fg") || given("palette"),
+        );
+        merge(
+            &mut args.bg,
+            self.bg.map(Some),
+            given("bg") || given("palette"),
+        );
+        merge(&mut args.scale, self.scale.map(Some), given("scale"));
+        args.stretch |= self.stretch.unwrap_or(false);
+        args.fade |= self.fade.unwrap_or(false);
+        args.crt |= self.crt.unwrap_or(false);
+        args.grid |= self.grid.unwrap_or(false);
+        merge(&mut args.grid_color, self.grid_color, given("grid_color"));
+
+        merge(
+            &mut args.keymap,
+            self.keymap.map(Some),
+            given("keymap") || given("layout"),
+        );
+        merge(&mut args.layout, self.layout, given("layout"));
+        merge(
+            &mut args.controller_map,
+            self.controller_map.map(Some),
+            given("controller_map"),
+        );
+
+        args.mute |= self.mute.unwrap_or(false);
+        merge(&mut args.volume, self.volume, given("volume"));
+        merge(&mut args.waveform, self.waveform, given("waveform"));
+        merge(&mut args.frequency, self.frequency, given("frequency"));
+
+        self.quirks.unwrap_or_default()
+    }
+}
+
+/// Reads a TOML config file, rejecting unknown settings and values the command line wouldn't
+/// accept. Relative paths in the file are relative to the file itself.
+pub(crate) fn load(path: &Path) -> Result<Config, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let mut config = parse(&text)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for file in config.paths_mut().into_iter().flatten() {
+        *file = dir.join(&*file);
+    }
+    Ok(config)
+}
+
+/// Writes a TOML config file, with absolute paths so it can be used from any directory.
+pub(crate) fn save(path: &Path, mut config: Config) -> Result<(), String> {
+    for file in config.paths_mut().into_iter().flatten() {
+        *file = std::path::absolute(&*file).map_err(|e| e.to_string())?;
+    }
+    let text = toml::to_string(&config).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| e.to_string())
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+    config.validate()?;
+    Ok(config)
+}
+
+fn merge<T>(arg: &mut T, value: Option<T>, given: bool) {
+    if let (Some(value), false) = (value, given) {
+        *arg = value;
+    }
+}
+
+fn check_range(key: &str, value: Option<u32>, range: RangeInclusive<u32>) -> Result<(), String> {
+    match value {
+        Some(value) if !range.contains(&value) => Err(if *range.end() == u32::MAX {
+            format!("{} must be at least {}", key, range.start())
+        } else {
+            format!("{} must be from {} to {}", key, range.start(), range.end())
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Stores the enums the command line takes by their names there, e.g. `memory = "xo-chip"`.
+mod value_enum {
+    use clap::ValueEnum;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: ValueEnum,
+    {
+        match value.as_ref().and_then(ValueEnum::to_possible_value) {
+            Some(value) => serializer.serialize_str(value.get_name()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: ValueEnum,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|name| T::from_str(&name, false).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Stores colours as `#RRGGBB`, as the command line takes them.
+mod color {
+    use sdl2::pixels::Color;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::palette::parse_color;
+
+    pub(super) fn serialize<S>(color: &Option<Color>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match color {
+            Some(c) => serializer.serialize_str(&format!("#{:02X}{:02X}{:02X}", c.r, c.g, c.b)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Color>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| parse_color(&s).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::DrawMode;
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+
+    fn args_with(text: &str, cli: &[&str]) -> (Args, Quirks) {
+        let matches = Args::command().try_get_matches_from(cli).unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let quirks = parse(text).unwrap().apply(&mut args, &matches);
+        (args, quirks)
+    }
+
+    #[test]
+    fn the_command_line_wins_over_the_file() {
+        let text = r##"
+            instructions-per-frame = 15
+            volume = 50
+            palette = "amber"
+            fg = "#FFAA00"
+            memory = "xo-chip"
+            fade = true
+
+            [quirks]
+            shift-uses-vy = true
+            draw-mode = "or"
+        "##;
+
+        let (args, quirks) = args_with(text, &["chip8", "-r", "game.ch8", "--volume", "10"]);
+        assert_eq!(args.instructions_per_frame(), 15);
+        assert_eq!(args.volume, 10);
+        assert_eq!(args.palette, Palette::Amber);
+        assert_eq!(args.fg, Some(Color::RGB(0xFF, 0xAA, 0x00)));
+        assert_eq!(args.memory, Memory::XoChip);
+        assert!(args.fade);
+        assert_eq!(
+            quirks,
+            Quirks {
+                shift_uses_vy: true,
+                draw_mode: DrawMode::Or,
+                ..Quirks::default()
+            }
+        );
+
+        let (args, _) = args_with(
+            text,
+            &[
+                "chip8",
+                "-r",
+                "game.ch8",
+                "--cycle-delay",
+                "2",
+                "--palette",
+                "lcd",
+            ],
+        );
+        assert_eq!(args.instructions_per_frame(), 8);
+        assert_eq!(args.palette, Palette::Lcd);
+        assert_eq!(args.fg, None);
+    }
+
+    #[test]
+    fn rejects_unknown_settings_and_bad_values() {
+        for (text, error) in [
+            ("volumes = 50", "unknown field `volumes`"),
+            ("rom-path = \"game.ch8\"", "unknown field `rom-path`"),
+            ("volume = 101", "volume must be from 0 to 100"),
+            ("scale = 0", "scale must be at least 1"),
+            ("fade = 1", "invalid type: integer `1`, expected a boolean"),
+            ("palette = \"pink\"", "Invalid variant: pink"),
+            ("fg = \"123\"", "expected a colour in #RRGGBB form"),
+            (
+                "[quirks]\nclip-sprites = true",
+                "unknown field `clip-sprites`",
+            ),
+        ] {
+            let e = parse(text).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}", text, e);
+        }
+    }
+
+    #[test]
+    fn relative_paths_are_relative_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("chip8-config-{}", std::process::id()));
+        let font = std::env::temp_dir().join("vip.bin");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chip8.toml");
+        let text = format!("keymap = \"keys.toml\"\nfont = {:?}", font);
+        fs::write(&path, text).unwrap();
+        let config = load(&path).unwrap();
+        assert_eq!(config.keymap, Some(dir.join("keys.toml")));
+        assert_eq!(config.font, Some(font));
+
+        let config = Config {
+            keymap: Some(PathBuf::from("keys.toml")),
+            ..Config::default()
+        };
+        save(&path, config).unwrap();
+        let saved = parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            saved.keymap,
+            Some(std::env::current_dir().unwrap().join("keys.toml"))
+        );
+    }
+
+    #[test]
+    fn saved_settings_load_back_unchanged() {
+        let cli = [
+            "chip8",
+            "-r",
+            "game.ch8",
+            "--fg",
+            "#FFAA00",
+            "--memory",
+            "xo-chip",
+            "--stretch",
+            "--keymap",
+            "keys.toml",
+        ];
+        let matches = Args::command().try_get_matches_from(cli).unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        let quirks = Quirks {
+            display_wraps: false,
+            ..Quirks::default()
+        };
+        let config = Config::from_args(&args, quirks);
+
+        assert_eq!(parse(&toml::to_string(&config).unwrap()).unwrap(), config);
+    }
+}
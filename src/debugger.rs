@@ -0,0 +1,388 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chip8::{Chip8, Chip8Error};
+
+use crate::rewind::Rewind;
+use crate::trace::Tracer;
+
+/// Parses an address given as hex, with or without a leading `0x`.
+pub(crate) fn parse_address(s: &str) -> Result<usize, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    usize::from_str_radix(digits, 16).map_err(|e| format!("Invalid address '{}': {}", s, e))
+}
+
+/// Pause, single-step and breakpoint state for the frontend.
+pub(crate) struct Debugger {
+    paused: bool,
+    step_requested: bool,
+    step_back_requested: bool,
+    frame_step_requested: bool,
+    /// Machine states from before each single-stepped instruction, for stepping back
+    history: Rewind,
+    breakpoints: BTreeSet<usize>,
+    /// Breakpoint we last stopped on, so that resuming doesn't immediately hit it again
+    last_break: Option<usize>,
+    commands: Option<Receiver<String>>,
+    /// Where the next `mem` without an address continues dumping from
+    memory_page: usize,
+}
+
+impl Debugger {
+    /// Instructions that can be stepped back over, bounding the memory the snapshots take.
+    const HISTORY_LENGTH: usize = 256;
+
+    pub(crate) fn new(start_paused: bool, breakpoints: &[usize]) -> Self {
+        // Only take over stdin when the user has asked for debugging
+        let commands = (start_paused || !breakpoints.is_empty()).then(|| {
+            println!("Debugger commands: break <addr>, delete <addr>, list, clear, mem [addr|-]");
+            spawn_command_reader()
+        });
+
+        Self {
+            paused: start_paused,
+            step_requested: false,
+            step_back_requested: false,
+            frame_step_requested: false,
+            history: Rewind::new(Self::HISTORY_LENGTH),
+            breakpoints: breakpoints.iter().copied().collect(),
+            last_break: None,
+            commands,
+            memory_page: Chip8::START_ADDRESS,
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(crate) fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.step_requested = false;
+        self.step_back_requested = false;
+        self.frame_step_requested = false;
+        if !self.paused {
+            // Running on makes the single-step history stale
+            self.history.clear();
+        }
+        println!("{}", if self.paused { "Paused" } else { "Running" });
+    }
+
+    pub(crate) fn request_step(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+
+    pub(crate) fn request_step_back(&mut self) {
+        if self.paused {
+            self.step_back_requested = true;
+        }
+    }
+
+    pub(crate) fn request_frame_step(&mut self) {
+        if self.paused {
+            self.frame_step_requested = true;
+        }
+    }
+
+    /// Whether a whole frame should run while paused, which the frontend does itself. Clears the
+    /// request.
+    pub(crate) fn take_frame_step(&mut self) -> bool {
+        if !std::mem::take(&mut self.frame_step_requested) {
+            return false;
+        }
+        // Stepping back would jump over the whole frame
+        self.history.clear();
+        self.last_break = None;
+        true
+    }
+
+    /// Runs a single instruction if a step was requested, printing it along with the resulting
+    /// machine state. A requested step back instead restores the state from before the last
+    /// stepped instruction, display and timers included.
+    pub(crate) fn step(&mut self, chip8: &mut Chip8, tracer: &mut Option<Tracer>) {
+        if self.step_back_requested {
+            self.step_back_requested = false;
+            self.last_break = None;
+            match self.history.pop() {
+                Some(state) => {
+                    *chip8 = state;
+                    chip8.force_redraw();
+                    let opcode = chip8.opcode();
+                    println!(
+                        "Stepped back to {:#06x}: {:04X}  {}",
+                        chip8.pc(),
+                        opcode,
+                        describe(opcode)
+                    );
+                    print_registers(chip8);
+                }
+                None => println!("No stepped instruction to step back over"),
+            }
+            return;
+        }
+        if !self.step_requested {
+            return;
+        }
+        self.step_requested = false;
+        self.last_break = None;
+
+        self.history.push(chip8);
+        let pc = chip8.pc();
+        match Tracer::cycle(tracer, chip8) {
+            Ok(opcode) => {
+                println!("{:#06x}: {:04X}  {}", pc, opcode, describe(opcode));
+                print_registers(chip8);
+            }
+            Err(e) => {
+                // A failed instruction leaves pc on it
+                let opcode = chip8.opcode();
+                println!("{:#06x}: {:04X}  {}: {}", pc, opcode, describe(opcode), e);
+            }
+        }
+    }
+
+    /// Pauses after an instruction failed, leaving `pc` on it so it can be inspected.
+    pub(crate) fn halt(&mut self, chip8: &Chip8, error: &Chip8Error) {
+        self.paused = true;
+        self.step_requested = false;
+        println!("Halted at {:#06x}: {}", chip8.pc(), error);
+        print_registers(chip8);
+    }
+
+    /// Checks whether the instruction about to run is a breakpoint, pausing if so. Must be called
+    /// before every `cycle` while running.
+    pub(crate) fn check_breakpoint(&mut self, chip8: &Chip8) -> bool {
+        let pc = chip8.pc();
+        if self.last_break.take() == Some(pc) || !self.breakpoints.contains(&pc) {
+            return false;
+        }
+
+        self.paused = true;
+        self.last_break = Some(pc);
+        let opcode = chip8.opcode();
+        println!(
+            "Breakpoint at {:#06x}: {:04X}  {}",
+            pc,
+            opcode,
+            describe(opcode)
+        );
+        print_registers(chip8);
+
+        true
+    }
+
+    /// Handles any commands typed into the console since the last call.
+    pub(crate) fn process_commands(&mut self, chip8: &Chip8) {
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        let lines = commands.try_iter().collect::<Vec<_>>();
+
+        for line in lines {
+            self.run_command(line.trim(), chip8);
+        }
+    }
+
+    fn run_command(&mut self, line: &str, chip8: &Chip8) {
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("break" | "b"), Some(addr)) => match parse_address(addr) {
+                Ok(addr) => {
+                    self.breakpoints.insert(addr);
+                    println!("Breakpoint set at {:#06x}", addr);
+                }
+                Err(e) => println!("{}", e),
+            },
+            (Some("delete" | "d"), Some(addr)) => match parse_address(addr) {
+                Ok(addr) if self.breakpoints.remove(&addr) => {
+                    println!("Breakpoint removed at {:#06x}", addr)
+                }
+                Ok(addr) => println!("No breakpoint at {:#06x}", addr),
+                Err(e) => println!("{}", e),
+            },
+            (Some("list" | "l"), None) => {
+                if self.breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for addr in &self.breakpoints {
+                    println!("  {:#06x}", addr);
+                }
+            }
+            (Some("clear"), None) => {
+                self.breakpoints.clear();
+                println!("Breakpoints cleared");
+            }
+            (Some("mem" | "m"), page) => {
+                let start = match page {
+                    None => self.memory_page,
+                    Some("-") => self.memory_page.saturating_sub(2 * MEMORY_PAGE),
+                    Some(addr) => match parse_address(addr) {
+                        Ok(addr) => addr & !0xF,
+                        Err(e) => return println!("{}", e),
+                    },
+                };
+                if chip8.memory_slice(start, 1).is_empty() {
+                    return println!("Address {:#06x} is outside memory", start);
+                }
+                print!("{}", memory_dump(chip8, start));
+                self.memory_page = start + MEMORY_PAGE;
+            }
+            (None, _) => {}
+            _ => println!("Unknown command '{}'", line),
+        }
+    }
+}
+
+/// Bytes shown by each `mem` command, as rows of 16.
+const MEMORY_PAGE: usize = 128;
+
+/// A page of memory from `start` as hex and ASCII, marking the byte at PC with `>` and the one at
+/// I with `*`.
+fn memory_dump(chip8: &Chip8, start: usize) -> String {
+    let mut dump = String::new();
+    for (row, bytes) in chip8
+        .memory_slice(start, MEMORY_PAGE)
+        .chunks(16)
+        .enumerate()
+    {
+        let address = start + row * 16;
+        let hex = bytes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let marker = match address + i {
+                    a if a == chip8.pc() => '>',
+                    a if a == chip8.index() => '*',
+                    _ => ' ',
+                };
+                format!("{}{:02x}", marker, b)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = bytes
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7E => b as char,
+                _ => '.',
+            })
+            .collect::<String>();
+
+        dump.push_str(&format!("{:#06x}: {}  |{}|\n", address, hex, ascii));
+    }
+    dump
+}
+
+fn spawn_command_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+fn describe(opcode: u16) -> String {
+    chip8::mnemonic(opcode).unwrap_or_else(|| "???".to_string())
+}
+
+pub(crate) fn print_registers(chip8: &Chip8) {
+    let registers = chip8
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{:X}={:02x}", i, v))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    println!("  {}", registers);
+    println!(
+        "  PC={:#06x} I={:#06x} SP={}",
+        chip8.pc(),
+        chip8.index(),
+        chip8.sp()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_back_over_stepped_instructions() {
+        // LD V0, 0x01; LD V0, 0x02
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01, 0x60, 0x02]).unwrap();
+        let mut debugger = Debugger::new(false, &[]);
+        debugger.toggle_pause();
+
+        for _ in 0..2 {
+            debugger.request_step();
+            debugger.step(&mut chip8, &mut None);
+        }
+        assert_eq!(chip8.registers()[0], 0x02);
+
+        debugger.request_step_back();
+        debugger.step(&mut chip8, &mut None);
+        assert_eq!((chip8.pc(), chip8.registers()[0]), (0x202, 0x01));
+
+        debugger.request_step_back();
+        debugger.step(&mut chip8, &mut None);
+        debugger.request_step_back();
+        debugger.step(&mut chip8, &mut None);
+        assert_eq!((chip8.pc(), chip8.registers()[0]), (0x200, 0x00));
+    }
+
+    #[test]
+    fn frame_steps_are_taken_once_and_only_while_paused() {
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01]).unwrap();
+        let mut debugger = Debugger::new(false, &[]);
+        debugger.request_frame_step();
+        assert!(!debugger.take_frame_step());
+
+        debugger.toggle_pause();
+        debugger.request_step();
+        debugger.step(&mut chip8, &mut None);
+        debugger.request_frame_step();
+        assert!(debugger.take_frame_step());
+        assert!(!debugger.take_frame_step());
+
+        // The frame moved past the stepped instruction, so it can't be stepped back over
+        debugger.request_step_back();
+        debugger.step(&mut chip8, &mut None);
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn dumps_memory_marking_pc_and_index() {
+        // LD I, 0x203; DB 0x41, 0x42
+        let mut chip8 = Chip8::from_bytes(&[0xA2, 0x03, 0x41, 0x42]).unwrap();
+        chip8.cycle().unwrap();
+
+        let dump = memory_dump(&chip8, 0x200);
+        assert_eq!(dump.lines().count(), 8);
+        assert_eq!(
+            dump.lines().next().unwrap(),
+            format!(
+                "0x0200:  a2  03 >41 *42{}  |..AB{}|",
+                "  00".repeat(12),
+                ".".repeat(12)
+            )
+        );
+    }
+}
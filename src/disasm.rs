@@ -0,0 +1,112 @@
+use crate::chip8::Chip8;
+use crate::instruction::{decode, Instruction};
+
+/// Renders a single opcode as an assembly mnemonic, or `None` if it isn't a known instruction.
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    match decode(opcode) {
+        Instruction::Unknown(_) => None,
+        instruction => Some(instruction.to_string()),
+    }
+}
+
+/// Disassembles a ROM into `(address, opcode, mnemonic)` triples, assuming it is loaded at the
+/// usual start address. Unknown opcodes, a trailing odd byte and an `F000` missing its operand
+/// are rendered as `DB` data, and the operand of a double-width `F000 nnnn` is folded into its
+/// mnemonic.
+pub fn disassemble(bytes: &[u8]) -> Vec<(usize, u16, String)> {
+    let mut listing = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = Chip8::START_ADDRESS + offset;
+
+        match bytes[offset..] {
+            [0xF0, 0x00, hi, lo, ..] => {
+                let addr = u16::from_be_bytes([hi, lo]);
+                listing.push((address, 0xF000, format!("LD I, LONG {:#06X}", addr)));
+                offset += 4;
+            }
+            [hi, lo, ..] => {
+                let opcode = u16::from_be_bytes([hi, lo]);
+                let mnemonic = match decode(opcode) {
+                    // Cut off before its address, so only data
+                    Instruction::LdILong => format!("DB {:#06X}", opcode),
+                    instruction => instruction.to_string(),
+                };
+                listing.push((address, opcode, mnemonic));
+                offset += 2;
+            }
+            [byte] => {
+                listing.push((address, byte as u16, format!("DB {:#04X}", byte)));
+                offset += 1;
+            }
+            [] => unreachable!("offset is within bounds"),
+        }
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn renders_mnemonics() {
+        assert_eq!(mnemonic(0x00E0).unwrap(), "CLS");
+        assert_eq!(mnemonic(0x12A4).unwrap(), "JP 0x2A4");
+        assert_eq!(mnemonic(0xD015).unwrap(), "DRW V0, V1, 5");
+        assert_eq!(mnemonic(0x6A2F).unwrap(), "LD VA, 0x2F");
+        assert_eq!(mnemonic(0xF365).unwrap(), "LD V3, [I]");
+        assert_eq!(mnemonic(0x5121), None);
+    }
+
+    #[test]
+    fn disassembles_rom() {
+        let listing = disassemble(&[0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0x12]);
+
+        assert_eq!(
+            listing,
+            vec![
+                (0x200, 0x00E0, "CLS".to_string()),
+                (0x202, 0xF000, "LD I, LONG 0x1234".to_string()),
+                (0x206, 0xFFFF, "DB 0xFFFF".to_string()),
+                (0x208, 0x12, "DB 0x12".to_string()),
+            ]
+        );
+        assert_eq!(disassemble(&[0xF0, 0x00])[0].2, "DB 0xF000");
+    }
+
+    /// Random opcodes mixed with `F000 nnnn` loads, which random bytes would almost never contain,
+    /// possibly ending in an odd byte or an `F000` cut off before its address.
+    fn roms() -> impl Strategy<Value = Vec<u8>> {
+        let piece = prop_oneof![
+            3 => any::<[u8; 2]>().prop_map(|opcode| opcode.to_vec()),
+            1 => any::<[u8; 2]>().prop_map(|[hi, lo]| vec![0xF0, 0x00, hi, lo]),
+        ];
+        let end = prop_oneof![
+            Just(vec![]),
+            any::<u8>().prop_map(|byte| vec![byte]),
+            Just(vec![0xF0, 0x00]),
+            any::<u8>().prop_map(|byte| vec![0xF0, 0x00, byte]),
+        ];
+        (proptest::collection::vec(piece, 0..32), end)
+            .prop_map(|(pieces, end)| pieces.into_iter().flatten().chain(end).collect())
+    }
+
+    proptest! {
+        /// Whole programs rather than single instructions, so `LD I, LONG`, unknown opcodes as
+        /// `DB` words and a trailing odd byte all have to survive the trip.
+        #[test]
+        fn disassembled_roms_assemble_back_to_the_same_bytes(rom in roms()) {
+            let source: Vec<String> = disassemble(&rom)
+                .into_iter()
+                .map(|(_, _, mnemonic)| mnemonic)
+                .collect();
+
+            prop_assert_eq!(crate::assemble(&source.join("\n")).unwrap(), rom);
+        }
+    }
+}
@@ -1,55 +1,649 @@
-#[derive(Debug)]
-pub(crate) struct Display {
-    video: [u32; Self::VIDEO_HEIGHT * Self::VIDEO_WIDTH],
+/// How a sprite is combined with the pixels already on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DrawMode {
+    /// Sprite pixels toggle the pixels under them, erasing lit ones, as every original
+    /// interpreter does
+    #[default]
+    Xor,
+    /// Sprite pixels light the pixels under them and never erase any. Drawing over lit pixels
+    /// still counts as a collision.
+    Or,
+}
+
+/// Each pixel holds a bitmask of the XO-CHIP planes it is lit in, so classic CHIP-8 pixels are
+/// either 0 or 1 and XO-CHIP pixels range from 0 to 3.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Display {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    video: [u32; Self::MAX_SIZE],
+    hires: bool,
+    /// Bitmask of the planes drawing and clearing operate on
+    planes: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     dirty: bool,
+    /// Indices of the pixels changed since the last `set_clean`, or `None` when the whole display
+    /// needs redrawing
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changed: Option<Vec<usize>>,
+}
+
+/// Displays are equal when they show the same thing, whether or not they still need redrawing.
+impl PartialEq for Display {
+    fn eq(&self, other: &Self) -> bool {
+        self.video == other.video && self.hires == other.hires && self.planes == other.planes
+    }
+}
+
+impl Eq for Display {}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display {
-    pub(crate) const VIDEO_HEIGHT: usize = 32;
-    pub(crate) const VIDEO_WIDTH: usize = 64;
-    pub(crate) const SIZE: usize = Self::VIDEO_HEIGHT * Self::VIDEO_WIDTH;
+    pub const VIDEO_HEIGHT: usize = 32;
+    pub const VIDEO_WIDTH: usize = 64;
+    pub const SIZE: usize = Self::VIDEO_HEIGHT * Self::VIDEO_WIDTH;
+    /// SUPER-CHIP high resolution mode dimensions
+    pub const HIRES_VIDEO_HEIGHT: usize = 64;
+    pub const HIRES_VIDEO_WIDTH: usize = 128;
+    pub const MAX_SIZE: usize = Self::HIRES_VIDEO_HEIGHT * Self::HIRES_VIDEO_WIDTH;
+    /// Number of XO-CHIP drawing planes
+    pub const PLANES: usize = 2;
 
     pub fn new() -> Self {
         Self {
-            video: [0; Self::SIZE],
+            video: [0; Self::MAX_SIZE],
+            hires: false,
+            planes: 0x1,
             dirty: true,
+            changed: None,
         }
     }
 
-    pub fn draw(&mut self, x_pos: usize, y_pos: usize, bytes: &[u8]) -> u8 {
-        let mut collision = 0;
+    pub fn width(&self) -> usize {
+        if self.hires {
+            Self::HIRES_VIDEO_WIDTH
+        } else {
+            Self::VIDEO_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            Self::HIRES_VIDEO_HEIGHT
+        } else {
+            Self::VIDEO_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between the 64x32 and 128x64 modes, clearing the screen as the SUPER-CHIP does.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.video.fill(0);
+        // The size changes even if the screen was already blank
+        self.force_redraw();
+    }
+
+    pub fn planes(&self) -> u32 {
+        self.planes
+    }
+
+    /// Selects which XO-CHIP planes subsequent draws and clears affect.
+    pub fn select_planes(&mut self, planes: u32) {
+        self.planes = planes & ((1 << Self::PLANES) - 1);
+    }
+
+    /// Draws an 8 pixel wide sprite into the selected planes, one byte per row, combining it with
+    /// the screen according to `mode`. Returns the number of rows in which the sprite hit a lit
+    /// pixel in any plane. `bytes` holds a separate sprite for each selected plane, lowest plane
+    /// first, as XO-CHIP lays them out. The starting position always wraps; pixels running off
+    /// the right or bottom edge wrap too if `wrap` is set and are dropped otherwise.
+    pub fn draw(
+        &mut self,
+        x_pos: usize,
+        y_pos: usize,
+        bytes: &[u8],
+        wrap: bool,
+        mode: DrawMode,
+    ) -> u8 {
+        self.draw_sprite(x_pos, y_pos, bytes, 1, wrap, mode)
+    }
+
+    /// Draws a 16 pixel wide SUPER-CHIP sprite into the selected planes, two bytes per row.
+    /// Planes, edges, modes and collisions behave as in [`Display::draw`].
+    pub fn draw_wide(
+        &mut self,
+        x_pos: usize,
+        y_pos: usize,
+        bytes: &[u8],
+        wrap: bool,
+        mode: DrawMode,
+    ) -> u8 {
+        self.draw_sprite(x_pos, y_pos, bytes, 2, wrap, mode)
+    }
+
+    fn draw_sprite(
+        &mut self,
+        x_pos: usize,
+        y_pos: usize,
+        bytes: &[u8],
+        row_bytes: usize,
+        wrap: bool,
+        mode: DrawMode,
+    ) -> u8 {
+        let (width, height) = (self.width(), self.height());
+        let (x_pos, y_pos) = (x_pos % width, y_pos % height);
+
+        let selected = self.planes;
+        let plane_bytes = bytes.len() / (selected.count_ones() as usize).max(1);
+        if plane_bytes == 0 {
+            return 0;
+        }
 
-        for (j, byte) in bytes.iter().enumerate() {
-            for i in 0..8 {
-                let x = (x_pos + i) % Self::VIDEO_WIDTH;
-                let y = (y_pos + j) % Self::VIDEO_HEIGHT;
+        // Rows are drawn across all planes at once, so a row counts once however many of its
+        // planes collided without keeping a list of rows
+        let mut collided_rows = 0;
+        for j in 0..plane_bytes.div_ceil(row_bytes) {
+            let mut collided = false;
+            let planes = (0..Self::PLANES)
+                .map(|plane| 1 << plane)
+                .filter(|bit| selected & bit != 0);
 
-                if (byte & (0x80 >> i)) != 0x0 {
-                    if self.video[y * Self::VIDEO_WIDTH + x] == 0x1 {
-                        collision = 1;
+            for (plane, sprite) in planes.zip(bytes.chunks(plane_bytes)) {
+                let Some(row) = sprite.chunks(row_bytes).nth(j) else {
+                    continue;
+                };
+                for (k, byte) in row.iter().enumerate() {
+                    for i in 0..8 {
+                        let (x, y) = (x_pos + 8 * k + i, y_pos + j);
+                        if !wrap && (x >= width || y >= height) {
+                            continue;
+                        }
+                        let (x, y) = (x % width, y % height);
+
+                        if (byte & (0x80 >> i)) != 0x0 {
+                            let index = y * width + x;
+                            let lit = self.video[index] & plane != 0;
+                            collided |= lit;
+                            match mode {
+                                DrawMode::Xor => self.video[index] ^= plane,
+                                DrawMode::Or if lit => continue,
+                                DrawMode::Or => self.video[index] |= plane,
+                            }
+                            self.dirty = true;
+                            self.mark_changed(index);
+                        }
                     }
-                    self.video[y * Self::VIDEO_WIDTH + x] ^= 0x1;
                 }
             }
+            collided_rows += collided as usize;
         }
-        self.dirty = true;
 
-        collision
+        collided_rows as u8
+    }
+
+    /// Number of pixels in the current mode a SUPER-CHIP scroll of `amount` moves by. Scroll
+    /// amounts are in hi-res pixels on real hardware, so low-res mode only moves half as far
+    /// (rounding down).
+    fn scroll_amount(&self, amount: usize) -> usize {
+        if self.hires {
+            amount
+        } else {
+            amount / 2
+        }
+    }
+
+    /// Scrolls the screen down by `lines` hi-res lines, blanking the rows at the top.
+    pub fn scroll_down(&mut self, lines: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = self.scroll_amount(lines).min(height);
+        let video = &mut self.video[..width * height];
+
+        video.copy_within(..(height - n) * width, n * width);
+        video[..n * width].fill(0);
+        self.force_redraw();
+    }
+
+    /// Scrolls the screen right by 4 hi-res pixels, blanking the columns on the left.
+    pub fn scroll_right(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        let n = self.scroll_amount(4);
+
+        for row in self.video[..width * height].chunks_exact_mut(width) {
+            row.copy_within(..width - n, n);
+            row[..n].fill(0);
+        }
+        self.force_redraw();
+    }
+
+    /// Scrolls the screen left by 4 hi-res pixels, blanking the columns on the right.
+    pub fn scroll_left(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        let n = self.scroll_amount(4);
+
+        for row in self.video[..width * height].chunks_exact_mut(width) {
+            row.copy_within(n.., 0);
+            row[width - n..].fill(0);
+        }
+        self.force_redraw();
     }
 
+    /// Blanks every plane. Clearing a blank screen leaves it clean.
     pub fn clear(&mut self) {
-        self.video.iter_mut().for_each(|i| *i = 0);
+        if self.video.iter().any(|&pixel| pixel != 0) {
+            self.video.fill(0);
+            self.force_redraw();
+        }
+    }
+
+    /// Blanks only the selected planes, as `00E0` does on the XO-CHIP. Leaves the display clean if
+    /// they were already blank.
+    pub fn clear_planes(&mut self) {
+        if self.video.iter().any(|&pixel| pixel & self.planes != 0) {
+            let keep = !self.planes;
+            self.video.iter_mut().for_each(|i| *i &= keep);
+            self.force_redraw();
+        }
+    }
+
+    /// Whether the pixels and selected planes only use planes that exist, as any display built
+    /// through drawing does. Restored save states may not.
+    #[cfg(feature = "serde")]
+    pub(crate) fn is_valid(&self) -> bool {
+        let planes = (1 << Self::PLANES) - 1;
+        self.planes & !planes == 0 && self.video.iter().all(|&pixel| pixel & !planes == 0)
     }
 
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
 
+    /// Marks the whole display as needing a redraw.
+    pub fn force_redraw(&mut self) {
+        self.dirty = true;
+        self.changed = None;
+    }
+
     pub fn set_clean(&mut self) {
         self.dirty = false;
+        match &mut self.changed {
+            Some(changed) => changed.clear(),
+            None => self.changed = Some(Vec::new()),
+        }
+    }
+
+    /// Indices into [`Display::view`] of the pixels drawn since the last
+    /// [`Display::set_clean`], or `None` if the display was cleared, scrolled or otherwise needs
+    /// redrawing in full. A pixel may be listed more than once.
+    pub fn changed_pixels(&self) -> Option<&[usize]> {
+        self.changed.as_deref()
     }
 
-    pub fn view(&self) -> &[u32; Self::SIZE] {
-        &self.video
+    fn mark_changed(&mut self, index: usize) {
+        if let Some(changed) = &mut self.changed {
+            // Past a screenful of changes a full redraw is cheaper, and this bounds the list when
+            // nothing is cleaning the display
+            if changed.len() < Self::MAX_SIZE {
+                changed.push(index);
+            } else {
+                self.changed = None;
+            }
+        }
+    }
+
+    /// Renders the current mode as RGBA bytes, each pixel scaled up to a `scale` x `scale` block.
+    /// `palette` gives the colour for each combination of lit planes, as for the frontend.
+    pub fn render_rgba(&self, scale: usize, palette: &[[u8; 4]; 4]) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let mut rgba = Vec::with_capacity(width * height * scale * scale * 4);
+
+        for row in self.view().chunks_exact(width) {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| palette[*pixel as usize].repeat(scale))
+                .collect();
+            for _ in 0..scale {
+                rgba.extend_from_slice(&line);
+            }
+        }
+        debug_assert_eq!(rgba.len(), width * height * scale * scale * 4);
+
+        rgba
+    }
+
+    /// Renders the current mode as text, one line per row with `█` for lit pixels (in any plane)
+    /// and a space for unlit ones.
+    pub fn render_ascii(&self) -> String {
+        let mut text = String::with_capacity((self.width() * 3 + 1) * self.height());
+
+        for row in self.view().chunks_exact(self.width()) {
+            text.extend(row.iter().map(|&pixel| if pixel != 0 { '█' } else { ' ' }));
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// The pixels of the current mode, row by row, `width() * height()` long.
+    pub fn view(&self) -> &[u32] {
+        &self.video[..self.width() * self.height()]
+    }
+
+    /// The 64x32 screen packed into one `u64` per row, leftmost pixel in the top bit as in sprite
+    /// data, with a bit set wherever any plane is lit. Far cheaper to compare or send than
+    /// [`Display::view`]. In hi-res mode it holds the top-left 64x32 pixels.
+    pub fn as_bitmask(&self) -> [u64; Self::VIDEO_HEIGHT] {
+        let mut rows = [0; Self::VIDEO_HEIGHT];
+        for (row, pixels) in rows.iter_mut().zip(self.video.chunks_exact(self.width())) {
+            *row = pixels[..Self::VIDEO_WIDTH]
+                .iter()
+                .fold(0, |bits, &pixel| bits << 1 | (pixel != 0) as u64);
+        }
+        rows
+    }
+
+    /// Hash of [`Display::view`], for checking frames against known-good ones.
+    pub fn hash(&self) -> u64 {
+        fnv1a(self.view())
+    }
+}
+
+/// 64-bit FNV-1a of the pixels, which unlike `DefaultHasher` is stable across Rust releases.
+fn fnv1a(pixels: &[u32]) -> u64 {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_le_bytes())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_hash_is_stable() {
+        assert_eq!(fnv1a(&[]), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(&[0x61]), 0xac804b820e4fe984);
+    }
+
+    #[test]
+    fn bitmask_matches_the_view() {
+        let mut display = Display::new();
+        display.draw(0, 0, &[0x80, 0x01], true, DrawMode::Xor);
+        display.draw(60, 31, &[0xFF], true, DrawMode::Xor);
+        display.select_planes(0x2);
+        display.draw(10, 5, &[0xC0], true, DrawMode::Xor);
+
+        let bitmask = display.as_bitmask();
+        assert_eq!(bitmask[..2], [1 << 63, 1 << 56]);
+        assert_eq!(bitmask[31], 0xF000_0000_0000_000F);
+        let unpacked: Vec<u32> = bitmask
+            .iter()
+            .flat_map(|row| (0..64).rev().map(move |bit| (row >> bit & 1) as u32))
+            .collect();
+        let lit: Vec<u32> = display.view().iter().map(|&p| (p != 0) as u32).collect();
+        assert_eq!(unpacked, lit);
+    }
+
+    #[test]
+    fn hires_changes_dimensions() {
+        let mut display = Display::new();
+        assert_eq!(display.view().len(), Display::SIZE);
+
+        display.set_hires(true);
+        assert_eq!(
+            (display.width(), display.height()),
+            (Display::HIRES_VIDEO_WIDTH, Display::HIRES_VIDEO_HEIGHT)
+        );
+        assert_eq!(display.view().len(), Display::MAX_SIZE);
+    }
+
+    #[test]
+    fn scrolls_in_hires() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        let width = Display::HIRES_VIDEO_WIDTH;
+        display.draw(8, 0, &[0x80], true, DrawMode::Xor);
+
+        display.scroll_down(3);
+        assert_eq!(display.view()[8], 0);
+        assert_eq!(display.view()[3 * width + 8], 1);
+
+        display.scroll_right();
+        assert_eq!(display.view()[3 * width + 12], 1);
+        assert_eq!(display.view().iter().sum::<u32>(), 1);
+
+        display.scroll_left();
+        display.scroll_left();
+        assert_eq!(display.view()[3 * width + 4], 1);
+        assert_eq!(display.view().iter().sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn scrolls_half_as_far_in_lores() {
+        let mut display = Display::new();
+        let width = Display::VIDEO_WIDTH;
+        display.draw(8, 0, &[0x80], true, DrawMode::Xor);
+
+        display.scroll_down(4);
+        assert_eq!(display.view()[2 * width + 8], 1);
+
+        display.scroll_right();
+        assert_eq!(display.view()[2 * width + 10], 1);
+
+        display.scroll_left();
+        assert_eq!(display.view()[2 * width + 8], 1);
+        assert_eq!(display.view().iter().sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn scroll_left_blanks_pixels_pushed_off_screen() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        display.draw(0, 0, &[0xF0], true, DrawMode::Xor);
+
+        display.scroll_left();
+
+        assert_eq!(display.view().iter().sum::<u32>(), 0);
+    }
+
+    #[test]
+    fn draws_into_selected_planes_only() {
+        let mut display = Display::new();
+        display.draw(0, 0, &[0xC0], true, DrawMode::Xor);
+
+        display.select_planes(0x2);
+        assert_eq!(display.draw(1, 0, &[0xC0], true, DrawMode::Xor), 0);
+        assert_eq!(display.view()[..3], [1, 3, 2]);
+
+        display.select_planes(0x3);
+        assert_eq!(display.draw(0, 0, &[0x80, 0x80], true, DrawMode::Xor), 1);
+        assert_eq!(display.view()[..3], [2, 3, 2]);
+
+        display.select_planes(0x1);
+        display.clear_planes();
+        assert_eq!(display.view()[..3], [2, 2, 2]);
+    }
+
+    #[test]
+    fn draws_a_separate_sprite_into_each_plane() {
+        let mut display = Display::new();
+        display.select_planes(0x2);
+        display.draw(0, 0, &[0xF0, 0xF0], true, DrawMode::Xor);
+
+        // Plane 1 gets the first two bytes and plane 2 the next two. Plane 1 is empty, so only
+        // plane 2's second row erases anything.
+        display.select_planes(0x3);
+        assert_eq!(
+            display.draw(0, 0, &[0xFF, 0xFF, 0x00, 0xF0], true, DrawMode::Xor),
+            1
+        );
+        assert_eq!(display.view()[..8], [3, 3, 3, 3, 1, 1, 1, 1]);
+        assert_eq!(display.view()[64..72], [1; 8]);
+
+        // Collisions in plane 1 alone are also reported
+        assert_eq!(
+            display.draw(0, 0, &[0x80, 0x00, 0x00, 0x00], true, DrawMode::Xor),
+            1
+        );
+    }
+
+    #[test]
+    fn hires_draw_wraps_at_128() {
+        let mut display = Display::new();
+        display.set_hires(true);
+
+        display.draw(124, 63, &[0xFF], true, DrawMode::Xor);
+
+        let view = display.view();
+        let row = 63 * Display::HIRES_VIDEO_WIDTH;
+        assert_eq!(view[row + 124..row + 128], [1, 1, 1, 1]);
+        assert_eq!(view[row..row + 4], [1, 1, 1, 1]);
+        assert_eq!(view[row + 4], 0);
+    }
+
+    #[test]
+    fn clipped_draw_drops_pixels_past_the_edges() {
+        let mut display = Display::new();
+        let width = Display::VIDEO_WIDTH;
+
+        assert_eq!(display.draw(63, 31, &[0xFF, 0xFF], false, DrawMode::Xor), 0);
+
+        let view = display.view();
+        assert_eq!(view[31 * width + 63], 1);
+        assert_eq!(view[31 * width], 0);
+        assert_eq!(view.iter().sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn clipped_draw_still_wraps_the_starting_position() {
+        let mut display = Display::new();
+
+        display.draw(
+            Display::VIDEO_WIDTH + 2,
+            Display::VIDEO_HEIGHT,
+            &[0x80],
+            false,
+            DrawMode::Xor,
+        );
+
+        assert_eq!(display.view()[2], 1);
+    }
+
+    #[test]
+    fn tracks_changed_pixels_until_cleared() {
+        let mut display = Display::new();
+        assert_eq!(display.changed_pixels(), None);
+
+        display.set_clean();
+        assert_eq!(display.changed_pixels(), Some(&[][..]));
+
+        display.draw(2, 1, &[0xA0], true, DrawMode::Xor);
+        let width = Display::VIDEO_WIDTH;
+        assert_eq!(display.changed_pixels(), Some(&[width + 2, width + 4][..]));
+
+        display.scroll_left();
+        assert_eq!(display.changed_pixels(), None);
+    }
+
+    #[test]
+    fn only_changes_mark_the_display_dirty() {
+        let mut display = Display::new();
+        display.set_clean();
+
+        display.draw(0, 0, &[0x00, 0x00], true, DrawMode::Xor);
+        assert!(!display.is_dirty());
+        display.clear();
+        assert!(!display.is_dirty());
+
+        display.draw(0, 0, &[0x80], true, DrawMode::Xor);
+        assert!(display.is_dirty());
+        display.set_clean();
+
+        display.select_planes(0x2);
+        display.clear_planes();
+        assert!(!display.is_dirty());
+        display.select_planes(0x1);
+        display.clear_planes();
+        assert!(display.is_dirty());
+    }
+
+    #[test]
+    fn xor_erases_overlapping_pixels_and_or_keeps_them() {
+        for (mode, row) in [(DrawMode::Xor, [1, 0, 1]), (DrawMode::Or, [1, 1, 1])] {
+            let mut display = Display::new();
+            assert_eq!(display.draw(0, 0, &[0xC0], true, mode), 0, "{:?}", mode);
+            display.set_clean();
+
+            // Both modes report drawing over the lit pixel as a collision
+            assert_eq!(display.draw(1, 0, &[0xC0], true, mode), 1, "{:?}", mode);
+            assert_eq!(display.view()[..3], row, "{:?}", mode);
+            assert!(display.is_dirty(), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn or_over_lit_pixels_changes_nothing() {
+        let mut display = Display::new();
+        display.draw(0, 0, &[0xF0], true, DrawMode::Or);
+        display.set_clean();
+
+        assert_eq!(display.draw(0, 0, &[0x60], true, DrawMode::Or), 1);
+        assert_eq!(display.view()[..5], [1, 1, 1, 1, 0]);
+        assert!(!display.is_dirty());
+    }
+
+    #[test]
+    fn renders_scaled_rgba() {
+        let mut display = Display::new();
+        display.draw(1, 0, &[0x80], true, DrawMode::Xor);
+        let palette = [[0, 0, 0, 255], [255, 255, 255, 255], [0; 4], [0; 4]];
+
+        let rgba = display.render_rgba(2, &palette);
+
+        let stride = Display::VIDEO_WIDTH * 2 * 4;
+        assert_eq!(rgba.len(), stride * Display::VIDEO_HEIGHT * 2);
+        for row in [0, stride] {
+            assert_eq!(rgba[row..row + 8], [0, 0, 0, 255, 0, 0, 0, 255]);
+            assert_eq!(rgba[row + 8..row + 16], [255; 8]);
+            assert_eq!(rgba[row + 16..row + 20], [0, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn renders_ascii_rows() {
+        let mut display = Display::new();
+        display.draw(0, 1, &[0xA0], true, DrawMode::Xor);
+
+        let text = display.render_ascii();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), Display::VIDEO_HEIGHT);
+        assert_eq!(lines[0], " ".repeat(Display::VIDEO_WIDTH));
+        assert_eq!(
+            lines[1],
+            format!("█ █{}", " ".repeat(Display::VIDEO_WIDTH - 3))
+        );
+
+        display.set_hires(true);
+        assert_eq!(
+            display.render_ascii().lines().count(),
+            Display::HIRES_VIDEO_HEIGHT
+        );
     }
 }
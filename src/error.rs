@@ -0,0 +1,70 @@
+use std::{error, fmt, io};
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    Io(io::Error),
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    #[cfg(feature = "serde")]
+    InvalidState(bincode::Error),
+    /// A save state decoded but holds values the machine can't be in, e.g. from a damaged file
+    #[cfg(feature = "serde")]
+    CorruptState(&'static str),
+    /// A `CALL` was made with all 16 stack levels in use
+    StackOverflow,
+    /// A `RET` was made with an empty stack
+    StackUnderflow,
+    /// The opcode isn't a CHIP-8, SUPER-CHIP or XO-CHIP instruction
+    UnknownOpcode(u16),
+    /// An instruction accessed memory past the end, at the given address
+    OutOfBounds(usize),
+    /// A ROM can't be loaded at an address over the font tables or past the end of memory
+    InvalidStartAddress(usize),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::Io(e) => write!(f, "IO error: {}", e),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes but at most {} bytes fit", size, max)
+            }
+            #[cfg(feature = "serde")]
+            Chip8Error::InvalidState(e) => write!(f, "Invalid save state: {}", e),
+            #[cfg(feature = "serde")]
+            Chip8Error::CorruptState(reason) => write!(f, "Corrupt save state: {}", reason),
+            Chip8Error::StackOverflow => write!(f, "Stack overflow"),
+            Chip8Error::StackUnderflow => write!(f, "Stack underflow"),
+            Chip8Error::UnknownOpcode(opcode) => write!(f, "Unknown opcode {:04X}", opcode),
+            Chip8Error::OutOfBounds(address) => {
+                write!(f, "Memory access out of bounds at {:#05x}", address)
+            }
+            Chip8Error::InvalidStartAddress(address) => {
+                write!(
+                    f,
+                    "Start address {:#05x} is inside the font tables or past the end of memory",
+                    address
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            #[cfg(feature = "serde")]
+            Chip8Error::InvalidState(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+
+/// The gzip magic number followed by the deflate compression method.
+const MAGIC: [u8; 3] = [0x1F, 0x8B, 0x08];
+
+/// Decompresses `rom` if it's gzipped, as ROM collections sometimes ship `.ch8.gz` files, and
+/// returns it untouched otherwise. Compressed ROMs are recognised by their header rather than
+/// their name so piped ROMs are handled too; a raw ROM would have to start with `JP 0xF8B`
+/// followed by an `08` byte to be mistaken for one.
+///
+/// Decompression stops past the largest memory a ROM can be loaded into, so a corrupt or
+/// malicious file can't expand without bound.
+pub fn decompress_rom(rom: Vec<u8>) -> Result<Vec<u8>, Chip8Error> {
+    if !rom.starts_with(&MAGIC) {
+        return Ok(rom);
+    }
+
+    let mut decompressed = Vec::new();
+    GzDecoder::new(rom.as_slice())
+        .take(Chip8::XO_CHIP_MEMORY_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    const ROM_SOURCE: &str = include_str!("../tests/roms/digits.asm");
+    const GZIPPED_ROM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms/digits.ch8.gz");
+
+    #[test]
+    fn gzipped_roms_load_like_the_raw_rom() {
+        let rom = assemble(ROM_SOURCE).unwrap();
+
+        let chip8 = Chip8::read_rom(GZIPPED_ROM).unwrap();
+        assert_eq!(chip8.memory_slice(Chip8::START_ADDRESS, rom.len()), rom);
+        assert_eq!(decompress_rom(rom.clone()).unwrap(), rom);
+    }
+
+    #[test]
+    fn corrupt_gzip_data_is_an_error() {
+        let mut rom = std::fs::read(GZIPPED_ROM).unwrap();
+        rom.truncate(rom.len() / 2);
+
+        assert!(matches!(decompress_rom(rom), Err(Chip8Error::Io(_))));
+    }
+}
@@ -0,0 +1,207 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use chip8::{Chip8, Chip8Error};
+
+use crate::debugger::print_registers;
+use crate::trace::Tracer;
+
+/// How long a headless run lasts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RunLength {
+    /// This many 60Hz frames
+    Frames(u64),
+    /// Exactly this many instructions, with the timers ticking once per frame's worth
+    Cycles(u64),
+}
+
+/// A value a register has to end a headless run with, for test ROMs that report their result in
+/// a register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RegisterExpectation {
+    register: usize,
+    value: u8,
+}
+
+/// Parses an expectation given as `V0=0x2A`, with the value in hex with a `0x` prefix or in
+/// decimal.
+pub(crate) fn parse_register_expectation(s: &str) -> Result<RegisterExpectation, String> {
+    let error = || format!("Invalid expectation '{}', expected e.g. V0=0x2A", s);
+    let (register, value) = s.split_once('=').ok_or_else(error)?;
+
+    let register = register
+        .strip_prefix(['V', 'v'])
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+        .ok_or_else(error)?;
+    let value = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(digits) => u8::from_str_radix(digits, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| error())?;
+
+    Ok(RegisterExpectation { register, value })
+}
+
+/// Runs a ROM without a window or audio for `length`, then prints a hash of the display, the
+/// number of instructions executed and the registers so test ROMs can be checked in CI. Runs
+/// limited by instruction count also print how long they took, as a benchmark. Fails if an
+/// instruction fails, the emulator panics or a register doesn't match its expectation.
+pub(crate) fn run_headless(
+    chip8: &mut Chip8,
+    length: RunLength,
+    instructions_per_frame: u32,
+    expectations: &[RegisterExpectation],
+    tracer: &mut Option<Tracer>,
+) -> ExitCode {
+    let started = Instant::now();
+    let mut executed = 0;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), Chip8Error> {
+        let mut frame = 0;
+        'frames: loop {
+            if length == RunLength::Frames(frame) {
+                break;
+            }
+            for _ in 0..instructions_per_frame {
+                if length == RunLength::Cycles(executed) {
+                    break 'frames;
+                }
+                Tracer::cycle(tracer, chip8)?;
+                executed += 1;
+                if chip8.is_waiting_for_vblank() {
+                    break;
+                }
+            }
+            chip8.tick_timers();
+            frame += 1;
+        }
+        Ok(())
+    }));
+    let elapsed = started.elapsed();
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            eprintln!("Emulation failed at PC={:#06x}: {}", chip8.pc(), e);
+            return ExitCode::FAILURE;
+        }
+        Err(_) => {
+            eprintln!("Emulation panicked at PC={:#06x}", chip8.pc());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!("video={:016x}", chip8.video_hash());
+    println!("cycles={}", chip8.cycle_count());
+    print_registers(chip8);
+    if let RunLength::Cycles(_) = length {
+        // Timing varies from run to run, so only benchmarks print it
+        println!("time={:.3}s", elapsed.as_secs_f64());
+        println!("ips={:.0}", executed as f64 / elapsed.as_secs_f64());
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for expectation in expectations {
+        let actual = chip8.registers()[expectation.register];
+        if actual != expectation.value {
+            eprintln!(
+                "Expected V{:X}=0x{:02X} but it was 0x{:02X}",
+                expectation.register, expectation.value, actual
+            );
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_exactly_the_requested_instructions() {
+        // LD V0, 0x01; JP 0x200
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+
+        let exit_code = run_headless(&mut chip8, RunLength::Cycles(25), 10, &[], &mut None);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        assert_eq!(chip8.cycle_count(), 25);
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn parses_register_expectations() {
+        assert_eq!(
+            parse_register_expectation("V0=0x2A"),
+            Ok(RegisterExpectation {
+                register: 0,
+                value: 0x2A
+            })
+        );
+        assert_eq!(
+            parse_register_expectation("vf=255"),
+            Ok(RegisterExpectation {
+                register: 0xF,
+                value: 0xFF
+            })
+        );
+        for s in ["V0", "V10=1", "VG=1", "X0=1", "V0=0x100", "V0=256", "V0=-1"] {
+            assert!(parse_register_expectation(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn fails_when_a_register_does_not_match() {
+        // LD V0, 0x2A; LD V1, 0x07
+        let rom = [0x60, 0x2A, 0x61, 0x07];
+        let expect = |s| parse_register_expectation(s).unwrap();
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        let expectations = [expect("V0=0x2A"), expect("V1=7")];
+        let exit_code = run_headless(
+            &mut chip8,
+            RunLength::Cycles(2),
+            10,
+            &expectations,
+            &mut None,
+        );
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+
+        let mut chip8 = Chip8::from_bytes(&rom).unwrap();
+        let expectations = [expect("V0=0x2A"), expect("V1=8")];
+        let exit_code = run_headless(
+            &mut chip8,
+            RunLength::Cycles(2),
+            10,
+            &expectations,
+            &mut None,
+        );
+        assert_eq!(exit_code, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory_fails() {
+        // JP 0xFFF runs off the end of memory
+        let mut chip8 = Chip8::from_bytes(&[0x1F, 0xFF]).unwrap();
+
+        assert_eq!(
+            run_headless(&mut chip8, RunLength::Frames(1), 2, &[], &mut None),
+            ExitCode::FAILURE
+        );
+    }
+
+    #[test]
+    fn reports_errors_as_failure() {
+        // CALL 0x200 recurses until the stack overflows
+        let mut chip8 = Chip8::from_bytes(&[0x22, 0x00]).unwrap();
+
+        assert_eq!(
+            run_headless(&mut chip8, RunLength::Frames(1), 20, &[], &mut None),
+            ExitCode::FAILURE
+        );
+    }
+}
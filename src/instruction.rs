@@ -0,0 +1,448 @@
+use std::fmt;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction. `x` and `y` name registers, `kk` is a
+/// byte operand, `n` a nibble and `nnn` a 12-bit address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00Cn
+    Scd { n: u8 },
+    /// 00FB
+    Scr,
+    /// 00FC
+    Scl,
+    /// 00FE
+    Low,
+    /// 00FF
+    High,
+    /// 1nnn
+    JpAddr(u16),
+    /// 2nnn
+    CallAddr(u16),
+    /// 3xkk
+    SeVxByte { x: u8, kk: u8 },
+    /// 4xkk
+    SneVxByte { x: u8, kk: u8 },
+    /// 5xy0
+    SeVxVy { x: u8, y: u8 },
+    /// 6xkk
+    LdVxByte { x: u8, kk: u8 },
+    /// 7xkk
+    AddVxByte { x: u8, kk: u8 },
+    /// 8xy0
+    LdVxVy { x: u8, y: u8 },
+    /// 8xy1
+    OrVxVy { x: u8, y: u8 },
+    /// 8xy2
+    AndVxVy { x: u8, y: u8 },
+    /// 8xy3
+    XorVxVy { x: u8, y: u8 },
+    /// 8xy4
+    AddVxVy { x: u8, y: u8 },
+    /// 8xy5
+    SubVxVy { x: u8, y: u8 },
+    /// 8xy6
+    ShrVxVy { x: u8, y: u8 },
+    /// 8xy7
+    SubnVxVy { x: u8, y: u8 },
+    /// 8xyE
+    ShlVxVy { x: u8, y: u8 },
+    /// 9xy0
+    SneVxVy { x: u8, y: u8 },
+    /// Annn
+    LdIAddr(u16),
+    /// Bnnn
+    JpV0Addr(u16),
+    /// Cxkk
+    RndVxByte { x: u8, kk: u8 },
+    /// Dxyn
+    DrwVxVyN { x: u8, y: u8, n: u8 },
+    /// Ex9E
+    SkpVx { x: u8 },
+    /// ExA1
+    SknpVx { x: u8 },
+    /// F000, followed by the address in the next two bytes
+    LdILong,
+    /// F002
+    Audio,
+    /// Fn01
+    Plane { n: u8 },
+    /// Fx07
+    LdVxDt { x: u8 },
+    /// Fx0A
+    LdVxK { x: u8 },
+    /// Fx15
+    LdDtVx { x: u8 },
+    /// Fx18
+    LdStVx { x: u8 },
+    /// Fx1E
+    AddIVx { x: u8 },
+    /// Fx29
+    LdFVx { x: u8 },
+    /// Fx30
+    LdHfVx { x: u8 },
+    /// Fx33
+    LdBVx { x: u8 },
+    /// Fx3A
+    PitchVx { x: u8 },
+    /// Fx55
+    LdIVx { x: u8 },
+    /// Fx65
+    LdVxI { x: u8 },
+    /// Fx75
+    LdRVx { x: u8 },
+    /// Fx85
+    LdVxR { x: u8 },
+    /// Not an instruction any supported interpreter defines
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// Number of different instructions, with all unknown opcodes counted as one.
+    pub const KINDS: usize = 47;
+
+    /// The opcode pattern of each instruction, indexed by [`Instruction::kind`]. Lowercase
+    /// letters stand for operands; unknown opcodes are `????`.
+    pub const PATTERNS: [&'static str; Self::KINDS] = [
+        "00E0", "00EE", "00Cn", "00FB", "00FC", "00FE", "00FF", "1nnn", "2nnn", "3xkk", "4xkk",
+        "5xy0", "6xkk", "7xkk", "8xy0", "8xy1", "8xy2", "8xy3", "8xy4", "8xy5", "8xy6", "8xy7",
+        "8xyE", "9xy0", "Annn", "Bnnn", "Cxkk", "Dxyn", "Ex9E", "ExA1", "F000", "F002", "Fn01",
+        "Fx07", "Fx0A", "Fx15", "Fx18", "Fx1E", "Fx29", "Fx30", "Fx33", "Fx3A", "Fx55", "Fx65",
+        "Fx75", "Fx85", "????",
+    ];
+
+    /// Which instruction this is regardless of its operands, from 0 to `KINDS - 1`, e.g. for
+    /// counting how often each one runs.
+    pub fn kind(&self) -> usize {
+        match self {
+            Instruction::Cls => 0,
+            Instruction::Ret => 1,
+            Instruction::Scd { .. } => 2,
+            Instruction::Scr => 3,
+            Instruction::Scl => 4,
+            Instruction::Low => 5,
+            Instruction::High => 6,
+            Instruction::JpAddr(_) => 7,
+            Instruction::CallAddr(_) => 8,
+            Instruction::SeVxByte { .. } => 9,
+            Instruction::SneVxByte { .. } => 10,
+            Instruction::SeVxVy { .. } => 11,
+            Instruction::LdVxByte { .. } => 12,
+            Instruction::AddVxByte { .. } => 13,
+            Instruction::LdVxVy { .. } => 14,
+            Instruction::OrVxVy { .. } => 15,
+            Instruction::AndVxVy { .. } => 16,
+            Instruction::XorVxVy { .. } => 17,
+            Instruction::AddVxVy { .. } => 18,
+            Instruction::SubVxVy { .. } => 19,
+            Instruction::ShrVxVy { .. } => 20,
+            Instruction::SubnVxVy { .. } => 21,
+            Instruction::ShlVxVy { .. } => 22,
+            Instruction::SneVxVy { .. } => 23,
+            Instruction::LdIAddr(_) => 24,
+            Instruction::JpV0Addr(_) => 25,
+            Instruction::RndVxByte { .. } => 26,
+            Instruction::DrwVxVyN { .. } => 27,
+            Instruction::SkpVx { .. } => 28,
+            Instruction::SknpVx { .. } => 29,
+            Instruction::LdILong => 30,
+            Instruction::Audio => 31,
+            Instruction::Plane { .. } => 32,
+            Instruction::LdVxDt { .. } => 33,
+            Instruction::LdVxK { .. } => 34,
+            Instruction::LdDtVx { .. } => 35,
+            Instruction::LdStVx { .. } => 36,
+            Instruction::AddIVx { .. } => 37,
+            Instruction::LdFVx { .. } => 38,
+            Instruction::LdHfVx { .. } => 39,
+            Instruction::LdBVx { .. } => 40,
+            Instruction::PitchVx { .. } => 41,
+            Instruction::LdIVx { .. } => 42,
+            Instruction::LdVxI { .. } => 43,
+            Instruction::LdRVx { .. } => 44,
+            Instruction::LdVxR { .. } => 45,
+            Instruction::Unknown(_) => 46,
+        }
+    }
+}
+
+/// Splits an opcode into its instruction and operands.
+pub fn decode(opcode: u16) -> Instruction {
+    // Dispatching on the top nibble through a table keeps each group's match small
+    GROUPS[(opcode >> 12) as usize](opcode)
+}
+
+/// Decoders for each value of an opcode's top nibble.
+const GROUPS: [fn(u16) -> Instruction; 16] = [
+    decode_0, decode_1, decode_2, decode_3, decode_4, decode_5, decode_6, decode_7, decode_8,
+    decode_9, decode_a, decode_b, decode_c, decode_d, decode_e, decode_f,
+];
+
+fn x(opcode: u16) -> u8 {
+    (opcode >> 8) as u8 & 0xF
+}
+
+fn y(opcode: u16) -> u8 {
+    (opcode >> 4) as u8 & 0xF
+}
+
+fn kk(opcode: u16) -> u8 {
+    opcode as u8
+}
+
+fn nnn(opcode: u16) -> u16 {
+    opcode & 0xFFF
+}
+
+fn decode_0(opcode: u16) -> Instruction {
+    match opcode {
+        0x00E0 => Instruction::Cls,
+        0x00EE => Instruction::Ret,
+        0x00C0..=0x00CF => Instruction::Scd {
+            n: opcode as u8 & 0xF,
+        },
+        0x00FB => Instruction::Scr,
+        0x00FC => Instruction::Scl,
+        0x00FE => Instruction::Low,
+        0x00FF => Instruction::High,
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+fn decode_1(opcode: u16) -> Instruction {
+    Instruction::JpAddr(nnn(opcode))
+}
+
+fn decode_2(opcode: u16) -> Instruction {
+    Instruction::CallAddr(nnn(opcode))
+}
+
+fn decode_3(opcode: u16) -> Instruction {
+    Instruction::SeVxByte {
+        x: x(opcode),
+        kk: kk(opcode),
+    }
+}
+
+fn decode_4(opcode: u16) -> Instruction {
+    Instruction::SneVxByte {
+        x: x(opcode),
+        kk: kk(opcode),
+    }
+}
+
+fn decode_5(opcode: u16) -> Instruction {
+    match opcode & 0xF {
+        0x0 => Instruction::SeVxVy {
+            x: x(opcode),
+            y: y(opcode),
+        },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+fn decode_6(opcode: u16) -> Instruction {
+    Instruction::LdVxByte {
+        x: x(opcode),
+        kk: kk(opcode),
+    }
+}
+
+fn decode_7(opcode: u16) -> Instruction {
+    Instruction::AddVxByte {
+        x: x(opcode),
+        kk: kk(opcode),
+    }
+}
+
+/// The register-to-register group, picked by the low nibble.
+fn decode_8(opcode: u16) -> Instruction {
+    let (x, y) = (x(opcode), y(opcode));
+    match opcode & 0xF {
+        0x0 => Instruction::LdVxVy { x, y },
+        0x1 => Instruction::OrVxVy { x, y },
+        0x2 => Instruction::AndVxVy { x, y },
+        0x3 => Instruction::XorVxVy { x, y },
+        0x4 => Instruction::AddVxVy { x, y },
+        0x5 => Instruction::SubVxVy { x, y },
+        0x6 => Instruction::ShrVxVy { x, y },
+        0x7 => Instruction::SubnVxVy { x, y },
+        0xE => Instruction::ShlVxVy { x, y },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+fn decode_9(opcode: u16) -> Instruction {
+    match opcode & 0xF {
+        0x0 => Instruction::SneVxVy {
+            x: x(opcode),
+            y: y(opcode),
+        },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+fn decode_a(opcode: u16) -> Instruction {
+    Instruction::LdIAddr(nnn(opcode))
+}
+
+fn decode_b(opcode: u16) -> Instruction {
+    Instruction::JpV0Addr(nnn(opcode))
+}
+
+fn decode_c(opcode: u16) -> Instruction {
+    Instruction::RndVxByte {
+        x: x(opcode),
+        kk: kk(opcode),
+    }
+}
+
+fn decode_d(opcode: u16) -> Instruction {
+    Instruction::DrwVxVyN {
+        x: x(opcode),
+        y: y(opcode),
+        n: opcode as u8 & 0xF,
+    }
+}
+
+fn decode_e(opcode: u16) -> Instruction {
+    let x = x(opcode);
+    match kk(opcode) {
+        0x9E => Instruction::SkpVx { x },
+        0xA1 => Instruction::SknpVx { x },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+/// The timer, memory and XO-CHIP group, picked by the low byte.
+fn decode_f(opcode: u16) -> Instruction {
+    let x = x(opcode);
+    match (x, kk(opcode)) {
+        (0x0, 0x00) => Instruction::LdILong,
+        (0x0, 0x02) => Instruction::Audio,
+        (n, 0x01) => Instruction::Plane { n },
+        (x, 0x07) => Instruction::LdVxDt { x },
+        (x, 0x0A) => Instruction::LdVxK { x },
+        (x, 0x15) => Instruction::LdDtVx { x },
+        (x, 0x18) => Instruction::LdStVx { x },
+        (x, 0x1E) => Instruction::AddIVx { x },
+        (x, 0x29) => Instruction::LdFVx { x },
+        (x, 0x30) => Instruction::LdHfVx { x },
+        (x, 0x33) => Instruction::LdBVx { x },
+        (x, 0x3A) => Instruction::PitchVx { x },
+        (x, 0x55) => Instruction::LdIVx { x },
+        (x, 0x65) => Instruction::LdVxI { x },
+        (x, 0x75) => Instruction::LdRVx { x },
+        (x, 0x85) => Instruction::LdVxR { x },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+/// Formats the instruction as an assembly mnemonic. Unknown opcodes are shown as `DB` data.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Scd { n } => write!(f, "SCD {}", n),
+            Instruction::Scr => write!(f, "SCR"),
+            Instruction::Scl => write!(f, "SCL"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::JpAddr(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::CallAddr(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SeVxByte { x, kk } => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Instruction::SneVxByte { x, kk } => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Instruction::SeVxVy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LdVxByte { x, kk } => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Instruction::AddVxByte { x, kk } => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Instruction::LdVxVy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::OrVxVy { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::AndVxVy { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::XorVxVy { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddVxVy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubVxVy { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShrVxVy { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubnVxVy { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShlVxVy { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneVxVy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdIAddr(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JpV0Addr(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::RndVxByte { x, kk } => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Instruction::DrwVxVyN { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkpVx { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SknpVx { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LdILong => write!(f, "LD I, LONG"),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::Plane { n } => write!(f, "PLANE {}", n),
+            Instruction::LdVxDt { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIVx { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdFVx { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LdHfVx { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdBVx { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::PitchVx { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::LdIVx { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LdVxI { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LdRVx { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LdVxR { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::Unknown(opcode) => write!(f, "DB {:#06X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_match_their_patterns() {
+        for opcode in 0..=u16::MAX {
+            let instruction = decode(opcode);
+            let pattern = Instruction::PATTERNS[instruction.kind()];
+            if let Instruction::Unknown(_) = instruction {
+                continue;
+            }
+
+            // Uppercase hex digits in the pattern are fixed, lowercase letters are operands
+            let fixed = pattern
+                .chars()
+                .zip(format!("{:04X}", opcode).chars())
+                .all(|(p, o)| p.is_ascii_lowercase() || p == o);
+            assert!(fixed, "{:04X} decoded as {}", opcode, pattern);
+        }
+    }
+
+    #[test]
+    fn decodes_operands() {
+        assert_eq!(decode(0x00C4), Instruction::Scd { n: 4 });
+        assert_eq!(decode(0x2ABC), Instruction::CallAddr(0xABC));
+        assert_eq!(decode(0x7A2F), Instruction::AddVxByte { x: 0xA, kk: 0x2F });
+        assert_eq!(decode(0x8126), Instruction::ShrVxVy { x: 1, y: 2 });
+        assert_eq!(decode(0xD01F), Instruction::DrwVxVyN { x: 0, y: 1, n: 0xF });
+        assert_eq!(decode(0xF301), Instruction::Plane { n: 3 });
+        assert_eq!(decode(0x5121), Instruction::Unknown(0x5121));
+    }
+
+    #[test]
+    fn mnemonics_assemble_back_to_their_opcode() {
+        for opcode in 0..=u16::MAX {
+            match decode(opcode) {
+                // Needs the address that follows it
+                Instruction::Unknown(_) | Instruction::LdILong => {}
+                instruction => assert_eq!(
+                    crate::assemble(&instruction.to_string()).unwrap(),
+                    opcode.to_be_bytes(),
+                    "{}",
+                    instruction
+                ),
+            }
+        }
+        assert_eq!(decode(0x5121).to_string(), "DB 0x5121");
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+
+/// Keys the event loop handles itself, which can't also be bound to the keypad.
+const RESERVED: [Keycode; 18] = [
+    Keycode::Escape,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F9,
+    Keycode::Backspace,
+    Keycode::Tab,
+    Keycode::M,
+    Keycode::P,
+    Keycode::N,
+    Keycode::Space,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::Minus,
+    Keycode::Equals,
+    Keycode::KpMinus,
+    Keycode::KpPlus,
+];
+
+/// The CHIP-8 keys in the 4x4 keypad order.
+const KEYPAD: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// Built-in keyboard layouts, each placing the keypad on the same physical keys, mirroring the
+/// COSMAC VIP keypad:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 4      1 2 3 C
+/// Q W E R      A Z E R  ->  4 5 6 D
+/// A S D F      Q S D F      7 8 9 E
+/// Z X C V      W X C V      A 0 B F
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Layout {
+    #[default]
+    Qwerty,
+    Azerty,
+}
+
+impl Layout {
+    /// The characters typed by the keys in the 4x4 block, row by row. The number row gives digits
+    /// regardless of layout.
+    fn chars(self) -> &'static str {
+        match self {
+            Layout::Qwerty => "1234qwerasdfzxcv",
+            Layout::Azerty => "1234azerqsdfwxcv",
+        }
+    }
+
+    pub(crate) fn keymap(self) -> HashMap<Keycode, usize> {
+        // SDL keycodes for digits and letters are their lowercase ASCII codes
+        self.chars()
+            .chars()
+            .zip(KEYPAD)
+            .filter_map(|(c, idx)| Some((Keycode::from_i32(c as i32)?, idx)))
+            .collect()
+    }
+
+    /// The same bindings by typed character, for terminals that report text rather than keys.
+    pub(crate) fn char_map(self) -> HashMap<char, usize> {
+        self.chars().chars().zip(KEYPAD).collect()
+    }
+}
+
+/// Reads a TOML keymap binding SDL key names to CHIP-8 keys, e.g. `Q = 0x4` or `"1" = "1"`.
+pub(crate) fn load_keymap(path: &Path) -> Result<HashMap<Keycode, usize>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    parse_keymap(&text, Keycode::from_name)
+}
+
+/// The D-pad drives the keys most games use for movement, with A and B as action buttons.
+pub(crate) fn default_controller_map() -> HashMap<Button, usize> {
+    HashMap::from([
+        (Button::DPadUp, 0x2),
+        (Button::DPadDown, 0x8),
+        (Button::DPadLeft, 0x4),
+        (Button::DPadRight, 0x6),
+        (Button::A, 0x5),
+        (Button::B, 0x0),
+    ])
+}
+
+/// Reads a TOML file binding SDL game controller button names to CHIP-8 keys, e.g. `dpup = 0x2`.
+/// Unlike the keyboard, buttons can be left unbound.
+pub(crate) fn load_controller_map(path: &Path) -> Result<HashMap<Button, usize>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let bindings = parse_bindings(&text, Button::from_string)?;
+
+    Ok(bindings.into_iter().map(|(_, b, idx)| (b, idx)).collect())
+}
+
+fn parse_keymap(
+    text: &str,
+    key_from_name: impl Fn(&str) -> Option<Keycode>,
+) -> Result<HashMap<Keycode, usize>, String> {
+    let bindings = parse_bindings(text, key_from_name)?;
+
+    if let Some((name, _, _)) = bindings.iter().find(|(_, key, _)| RESERVED.contains(key)) {
+        return Err(format!(
+            "\"{}\" is reserved for the emulator controls",
+            name
+        ));
+    }
+
+    let keymap: HashMap<Keycode, usize> = bindings
+        .into_iter()
+        .map(|(_, key, idx)| (key, idx))
+        .collect();
+
+    let missing: Vec<String> = (0..16)
+        .filter(|idx| !keymap.values().any(|i| i == idx))
+        .map(|idx| format!("{:X}", idx))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("No key bound to {}", missing.join(", ")));
+    }
+
+    Ok(keymap)
+}
+
+/// Parses a TOML table of input names to CHIP-8 keys, rejecting unknown names, values outside
+/// 0-F and inputs bound more than once.
+fn parse_bindings<K: PartialEq>(
+    text: &str,
+    from_name: impl Fn(&str) -> Option<K>,
+) -> Result<Vec<(String, K, usize)>, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let mut bindings: Vec<(String, K, usize)> = Vec::with_capacity(table.len());
+
+    for (name, value) in table {
+        let input = from_name(&name).ok_or_else(|| format!("Unknown key name \"{}\"", name))?;
+
+        let idx = match value {
+            toml::Value::Integer(n) => usize::try_from(n).ok(),
+            toml::Value::String(s) => usize::from_str_radix(&s, 16).ok(),
+            _ => None,
+        }
+        .filter(|idx| *idx < 16)
+        .ok_or_else(|| format!("\"{}\" must map to a hex key from 0 to F", name))?;
+
+        if bindings.iter().any(|(_, bound, _)| *bound == input) {
+            return Err(format!("\"{}\" is bound more than once", name));
+        }
+        bindings.push((name, input, idx));
+    }
+
+    Ok(bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolves names without going through SDL, which needs the native library at runtime.
+    fn key_from_name(name: &str) -> Option<Keycode> {
+        Layout::Qwerty
+            .keymap()
+            .into_keys()
+            .chain(RESERVED)
+            .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+    }
+
+    const QWERTY_TOML: &str = r#"
+        Num1 = 0x1
+        Num2 = 0x2
+        Num3 = 0x3
+        Num4 = "C"
+        Q = 0x4
+        W = 0x5
+        E = 0x6
+        R = "d"
+        A = 0x7
+        S = 0x8
+        D = 0x9
+        F = 0xE
+        Z = 0xA
+        X = 0x0
+        C = 0xB
+        V = 0xF
+    "#;
+
+    #[test]
+    fn parses_full_keymap() {
+        assert_eq!(
+            parse_keymap(QWERTY_TOML, key_from_name),
+            Ok(Layout::Qwerty.keymap())
+        );
+    }
+
+    #[test]
+    fn azerty_swaps_letters_only() {
+        let azerty = Layout::Azerty.keymap();
+
+        assert_eq!(azerty.len(), 16);
+        assert_eq!(azerty[&Keycode::A], 0x4);
+        assert_eq!(azerty[&Keycode::Z], 0x5);
+        assert_eq!(azerty[&Keycode::Q], 0x7);
+        assert_eq!(azerty[&Keycode::W], 0xA);
+        assert_eq!(azerty[&Keycode::Num4], 0xC);
+    }
+
+    #[test]
+    fn controller_bindings_may_be_partial() {
+        let from_name = |name: &str| match name {
+            "dpup" => Some(Button::DPadUp),
+            "a" => Some(Button::A),
+            _ => None,
+        };
+
+        let bindings = parse_bindings("dpup = 0x2\na = \"5\"", from_name).unwrap();
+        assert_eq!(
+            bindings,
+            [
+                ("a".to_string(), Button::A, 0x5),
+                ("dpup".to_string(), Button::DPadUp, 0x2)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_keys() {
+        let text = QWERTY_TOML.replace("V = 0xF", "");
+
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("No key bound to F".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_and_unknown_keys() {
+        let text = format!("{}\nq = 0x1", QWERTY_TOML);
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("\"q\" is bound more than once".to_string())
+        );
+
+        let text = QWERTY_TOML.replace("V = 0xF", "Nope = 0xF");
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("Unknown key name \"Nope\"".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_keys_and_bad_values() {
+        let text = QWERTY_TOML.replace("V = 0xF", "P = 0xF");
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("\"P\" is reserved for the emulator controls".to_string())
+        );
+
+        let text = QWERTY_TOML.replace("V = 0xF", "V = 16");
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("\"V\" must map to a hex key from 0 to F".to_string())
+        );
+    }
+
+    #[test]
+    fn speed_keys_are_reserved() {
+        for name in ["Minus", "Equals", "KpMinus", "KpPlus"] {
+            let text = QWERTY_TOML.replace("V = 0xF", &format!("{} = 0xF", name));
+            assert_eq!(
+                parse_keymap(&text, key_from_name),
+                Err(format!(
+                    "\"{}\" is reserved for the emulator controls",
+                    name
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn frame_step_key_is_reserved() {
+        let text = QWERTY_TOML.replace("V = 0xF", "N = 0xF");
+        assert_eq!(
+            parse_keymap(&text, key_from_name),
+            Err("\"N\" is reserved for the emulator controls".to_string())
+        );
+    }
+}
@@ -1,7 +1,24 @@
+mod asm;
 mod chip8;
+mod disasm;
 mod display;
+mod error;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod instruction;
 mod lsfr;
-mod screen;
+mod quirks;
+mod rng;
+#[cfg(feature = "serde")]
+mod serde_array;
 
-pub use chip8::Chip8;
-pub use screen::run_chip8;
+pub use asm::{assemble, AsmError};
+pub use chip8::{Chip8, UnknownOpcodePolicy};
+pub use disasm::{disassemble, mnemonic};
+pub use display::{Display, DrawMode};
+pub use error::Chip8Error;
+#[cfg(feature = "gzip")]
+pub use gzip::decompress_rom;
+pub use instruction::{decode, Instruction};
+pub use quirks::Quirks;
+pub use rng::{Rng, RngClone, XorShift};
@@ -1,11 +1,35 @@
-#[derive(Debug)]
+use crate::rng::Rng;
+
+/// 16-bit Fibonacci linear feedback shift register with taps at bits 16, 14, 13 and 11
+/// (x^16 + x^14 + x^13 + x^11 + 1), the maximal-length example register from the Wikipedia
+/// article on LFSRs. It steps through all 65535 nonzero states before repeating.
+///
+/// No CHIP-8 interpreter specifies a generator to match: the COSMAC VIP's `Cxkk` mixes a byte of
+/// the interpreter's own code with a counter rather than using a shift register, and later
+/// interpreters use whatever their platform offers. This register is kept because it's cheap and
+/// its exact sequence, pinned down by the tests below, is what seeded runs and recordings rely on.
+#[derive(Clone, Debug)]
 pub(crate) struct Lsfr(u16);
 
 impl Lsfr {
+    pub(crate) const DEFAULT_SEED: u16 = 0x1234;
+
     pub fn new() -> Self {
-        Self(0x1234)
+        Self(Self::DEFAULT_SEED)
     }
 
+    /// Starts the register at `seed`. An all-zero register would only ever produce zeros, so a
+    /// seed of 0 falls back to the default.
+    pub fn with_seed(seed: u16) -> Self {
+        if seed == 0 {
+            Self::new()
+        } else {
+            Self(seed)
+        }
+    }
+
+    /// Shifts the register right by one, feeding the XOR of the tapped bits (bits 0, 2, 3 and 5
+    /// before the shift) in at the top, and returns that new bit.
     fn get(&mut self) -> u8 {
         let bit = (self.0 ^ (self.0 >> 2) ^ (self.0 >> 3) ^ (self.0 >> 5)) & 1;
         self.0 = (self.0 >> 1) | (bit << 15);
@@ -13,15 +37,30 @@ impl Lsfr {
         bit as u8
     }
 
+    /// Builds a byte from the next eight bits, the first bit becoming the least significant.
     pub fn gen(&mut self) -> u8 {
         let mut r = 0;
         for i in 0..8 {
-            r += self.get() << i;
+            r |= self.get() << i;
         }
         r
     }
 }
 
+impl Rng for Lsfr {
+    fn next_byte(&mut self) -> u8 {
+        self.gen()
+    }
+
+    fn state(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        *self = Self::with_seed(state as u16);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,13 +77,42 @@ mod tests {
     }
 
     #[test]
-    fn it_works2() {
+    fn sequence_is_pinned() {
+        let mut x = Lsfr::new();
+
+        assert_eq!(
+            (0..8).map(|_| x.gen()).collect::<Vec<_>>(),
+            [110, 36, 219, 80, 112, 204, 129, 136]
+        );
+    }
+
+    #[test]
+    fn cycles_through_every_nonzero_state() {
         let mut x = Lsfr::new();
 
+        for step in 1..65535 {
+            x.get();
+            assert_ne!(x.0, Lsfr::DEFAULT_SEED, "repeated after {} steps", step);
+        }
+        x.get();
+        assert_eq!(x.0, Lsfr::DEFAULT_SEED);
+    }
+
+    #[test]
+    fn seeds_change_the_sequence() {
+        let mut a = Lsfr::with_seed(0xBEEF);
+        let mut b = Lsfr::new();
+
+        assert_ne!(
+            (0..4).map(|_| a.gen()).collect::<Vec<_>>(),
+            (0..4).map(|_| b.gen()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn zero_seed_is_remapped() {
+        let mut x = Lsfr::with_seed(0);
+
         assert_eq!(x.gen(), 110);
-        assert_eq!(x.gen(), 36);
-        assert_eq!(x.gen(), 219);
-        assert_eq!(x.gen(), 80);
-        assert_eq!(x.gen(), 112);
     }
 }
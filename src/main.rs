@@ -1,25 +1,824 @@
-use chip8::{run_chip8, Chip8};
-use clap::Parser;
+mod audio;
+mod capture;
+#[cfg(feature = "serde")]
+mod config;
+mod debugger;
+mod headless;
+mod keymap;
+mod overlay;
+mod palette;
+mod rewind;
+mod screen;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod tas;
+mod trace;
+mod tui;
+#[cfg(feature = "watch")]
+mod watch;
+
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8::{Chip8, Display, DrawMode, Instruction, Quirks, UnknownOpcodePolicy, XorShift};
+use clap::{Args as ClapArgs, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use sdl2::pixels::Color;
+
+use crate::audio::Waveform;
+use crate::headless::{RegisterExpectation, RunLength};
+use crate::keymap::Layout;
+use crate::palette::Palette;
+use crate::screen::{run_chip8, RunOptions};
+use crate::tas::{InputLog, InputPlayback, InputRecorder, Session};
+use crate::trace::Tracer;
 
 /// Chip8 emulator
 #[derive(Parser, Debug)]
 #[command(author, version,about, long_about=None)]
 struct Args {
-    /// Rom path
-    #[arg(short, long)]
-    rom_path: String,
+    /// Rom path, or - to read the ROM from stdin
+    #[cfg_attr(
+        feature = "serde",
+        arg(short, long, required_unless_present_any = ["assemble", "save_config"])
+    )]
+    #[cfg_attr(
+        not(feature = "serde"),
+        arg(short, long, required_unless_present = "assemble")
+    )]
+    rom_path: Option<String>,
 
-    /// Cycle delay in milliseconds
+    /// Cycle delay in milliseconds (legacy, prefer --instructions-per-frame)
     #[arg(short, long, default_value_t = 10)]
     cycle_delay: u32,
+
+    /// Instructions executed per 60Hz frame, e.g. 7-15 for classic games or 1000+ for SCHIP
+    /// titles. Overrides --cycle-delay when given
+    #[arg(
+        short,
+        long = "instructions-per-frame",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    ipf: Option<u32>,
+
+    /// Speed multiplier while fast-forwarding (hold Tab)
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    turbo_factor: u32,
+
+    /// Make - and + only change the instructions per frame, keeping the timers at 60Hz, instead of
+    /// slowing down or speeding up the whole machine
+    #[arg(long)]
+    speed_cpu_only: bool,
+
+    /// Number of frames kept for rewinding (hold Backspace), 0 disables rewinding
+    #[arg(long, default_value_t = 600)]
+    rewind_frames: usize,
+
+    /// Start paused in step mode: Space executes one instruction, P resumes
+    #[arg(short, long)]
+    debug: bool,
+
+    /// Keep running and beeping while the window is in the background
+    #[arg(long)]
+    no_pause_on_blur: bool,
+
+    /// Pause in step mode when execution reaches this address, e.g. 0x2A4. Can be repeated
+    #[arg(short, long = "breakpoint", value_parser = debugger::parse_address)]
+    breakpoints: Vec<usize>,
+
+    /// Amount of memory, 4KB for classic programs or 64KB for XO-CHIP ones
+    #[arg(long, value_enum, default_value_t)]
+    memory: Memory,
+
+    /// Address the ROM is loaded at and execution starts from, past the fonts, e.g. 0x600 for ETI-660
+    /// programs
+    #[arg(long, value_parser = debugger::parse_address)]
+    start_address: Option<usize>,
+
+    /// Reload and reset the ROM whenever its file changes, e.g. while developing it
+    #[cfg(feature = "watch")]
+    #[arg(long)]
+    watch: bool,
+
+    /// Print a disassembly of the ROM and exit
+    #[arg(long)]
+    disassemble: bool,
+
+    /// Assemble this source file into a ROM written to --output and exit
+    #[arg(long, requires = "output")]
+    assemble: Option<PathBuf>,
+
+    /// ROM file written by --assemble
+    #[arg(short, long, requires = "assemble")]
+    output: Option<PathBuf>,
+
+    /// TOML file of settings named after their flags, e.g. `volume = 50`, with the quirks under
+    /// `[quirks]`. Flags given on the command line take precedence over the file
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Write the settings this run would use, from the command line, --config and the defaults, to
+    /// this TOML file, then exit unless a ROM is given
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    save_config: Option<PathBuf>,
+
+    /// File the SUPER-CHIP RPL user flags are loaded from and saved to, e.g. for high scores
+    #[arg(long)]
+    flags_file: Option<PathBuf>,
+
+    /// TOML file binding SDL key names to CHIP-8 keys, e.g. `Q = 0x4`. All 16 keys must be bound
+    #[arg(long)]
+    keymap: Option<PathBuf>,
+
+    /// Built-in keyboard layout the keypad is mapped onto, ignored when --keymap is given
+    #[arg(long, value_enum, default_value_t)]
+    layout: Layout,
+
+    /// TOML file binding SDL game controller button names to CHIP-8 keys, e.g. `dpup = 0x2`
+    #[arg(long)]
+    controller_map: Option<PathBuf>,
+
+    /// Size the window to fit the 64x32 display scaled by this whole number
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: Option<u32>,
+
+    /// Stretch the display to fill the window instead of keeping pixels square
+    #[arg(long)]
+    stretch: bool,
+
+    /// Let pixels fade out over a few frames when they turn off, reducing flicker
+    #[arg(long)]
+    fade: bool,
+
+    /// Darken scanlines and add a slight glow in the window, like a CRT. Scanlines need pixels at
+    /// least 3 window pixels tall
+    #[arg(long)]
+    crt: bool,
+
+    /// Draw lines between the pixels, for designing sprites and checking positions. Needs pixels
+    /// at least 3 window pixels across
+    #[arg(long)]
+    grid: bool,
+
+    /// Colour of the --grid lines, as #RRGGBB
+    #[arg(long, value_parser = palette::parse_color, default_value = "#404040", requires = "grid")]
+    grid_color: Color,
+
+    /// Directory screenshots (F2) and recordings (F3) are saved to
+    #[arg(long, default_value = ".")]
+    capture_dir: PathBuf,
+
+    /// How many times larger than the CHIP-8 display screenshots and recordings are saved
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    capture_scale: u32,
+
+    /// Record the session to this GIF file from startup; F3 stops and starts recording
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Frame rate recordings are captured at, rounded to a whole fraction of 60
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=60))]
+    record_fps: u32,
+
+    /// Colour scheme, see --help for the presets
+    #[arg(long, value_enum, default_value_t)]
+    palette: Palette,
+
+    /// Colour of lit pixels, as #RRGGBB, overriding the palette
+    #[arg(long, value_parser = palette::parse_color)]
+    fg: Option<Color>,
+
+    /// Background colour, as #RRGGBB, overriding the palette
+    #[arg(long, value_parser = palette::parse_color)]
+    bg: Option<Color>,
+
+    /// Seed for the random number generator, e.g. to replay the same game. Must not be 0.
+    /// Defaults to a seed taken from the system clock
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    seed: Option<u16>,
+
+    /// Use the emulator's fixed built-in seed, so every run is identical
+    #[arg(long, conflicts_with = "seed")]
+    deterministic: bool,
+
+    /// Random number generator behind RND Vx, kk
+    #[arg(long, value_enum, default_value_t)]
+    rng: Generator,
+
+    /// Make RND Vx, kk always load kk, taking randomness out entirely. A testing aid for getting
+    /// stable frames from ROMs that draw random numbers; games won't play properly
+    #[arg(
+        long,
+        conflicts_with_all = ["seed", "deterministic", "rng", "record_input", "play_input"]
+    )]
+    no_rng: bool,
+
+    /// File of 80 bytes replacing the built-in hex digit font, 5 bytes per digit from 0 to F
+    #[arg(long)]
+    font: Option<PathBuf>,
+
+    /// What to do on an opcode no supported interpreter defines
+    #[arg(long, value_enum, default_value_t)]
+    on_unknown: OnUnknown,
+
+    /// Write every executed instruction, as --disassemble lists it, and the registers it changed
+    /// to this file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Start with the beep silenced. M toggles it while running
+    #[arg(long)]
+    mute: bool,
+
+    /// Beep volume in percent. [ and ] turn it down and up while running
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+
+    /// Shape of the beep
+    #[arg(long, value_enum, default_value_t)]
+    waveform: Waveform,
+
+    /// Pitch of the beep in Hz
+    #[arg(long, default_value_t = 440, value_parser = clap::value_parser!(u16).range(20..=20000))]
+    frequency: u16,
+
+    /// Record the keypad input to this file, along with the seed and speed, for --play-input
+    #[arg(long, conflicts_with_all = ["play_input", "headless"])]
+    record_input: Option<PathBuf>,
+
+    /// Replay keypad input recorded with --record-input, with the seed and speed it was recorded
+    /// at. Other input is ignored apart from quitting
+    #[arg(long, conflicts_with = "headless")]
+    play_input: Option<PathBuf>,
+
+    /// Count how many times each instruction runs and print a histogram on exit
+    #[arg(long)]
+    profile_opcodes: bool,
+
+    /// Print the measured instructions per second every second
+    #[arg(long)]
+    stats: bool,
+
+    /// Draw the measured frames and instructions per second in the corner of the window
+    #[arg(long)]
+    show_fps: bool,
+
+    /// Frontend to run the emulator in
+    #[arg(long, value_enum, default_value_t)]
+    backend: Backend,
+
+    /// Run without a window or sound for --frames frames, then print a hash of the display and
+    /// the registers. Uses the fixed seed unless --seed is given
+    #[arg(long)]
+    headless: bool,
+
+    /// Number of 60Hz frames to run in --headless mode
+    #[arg(long, default_value_t = 600, requires = "headless")]
+    frames: u64,
+
+    /// Run exactly this many instructions in --headless mode instead of --frames, then print how
+    /// long they took and the instructions per second
+    #[arg(long, requires = "headless", conflicts_with = "frames")]
+    exit_after_cycles: Option<u64>,
+
+    /// Fail a --headless run unless the register ends with this value, e.g. V0=0x2A. Can be
+    /// given more than once
+    #[arg(
+        long = "expect-register",
+        value_name = "REGISTER=VALUE",
+        requires = "headless",
+        value_parser = headless::parse_register_expectation
+    )]
+    expected_registers: Vec<RegisterExpectation>,
+
+    #[command(flatten)]
+    quirks: QuirkArgs,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// SDL window with sound and game controller support
+    #[default]
+    Sdl,
+    /// Half-block characters in the terminal, with only the keypad and Esc to quit
+    Tui,
 }
 
-fn main() {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Generator {
+    /// 16-bit linear feedback shift register, as the emulator has always used
+    #[default]
+    Lfsr,
+    /// 32-bit xorshift, with much better randomness but no basis in real hardware
+    Xorshift,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum OnUnknown {
+    /// Crash the emulator
+    Panic,
+    /// Skip the opcode and carry on
+    Nop,
+    /// Stop and pause in the debugger, or exit with an error when headless
+    #[default]
+    Halt,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Memory {
+    /// 4KB
+    #[default]
+    Classic,
+    /// 64KB
+    XoChip,
+}
+
+impl Memory {
+    fn size(self) -> usize {
+        match self {
+            Memory::Classic => Chip8::MEMORY_SIZE,
+            Memory::XoChip => Chip8::XO_CHIP_MEMORY_SIZE,
+        }
+    }
+}
+
+impl From<OnUnknown> for UnknownOpcodePolicy {
+    fn from(on_unknown: OnUnknown) -> Self {
+        match on_unknown {
+            OnUnknown::Panic => UnknownOpcodePolicy::Panic,
+            OnUnknown::Nop => UnknownOpcodePolicy::Nop,
+            OnUnknown::Halt => UnknownOpcodePolicy::Halt,
+        }
+    }
+}
+
+/// Interpreter quirks, for ROMs written against the original COSMAC VIP behaviour
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "Quirks")]
+struct QuirkArgs {
+    /// 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    #[arg(long)]
+    shift_uses_vy: bool,
+
+    /// Fx55/Fx65 increment I by x + 1
+    #[arg(long)]
+    load_store_increments_index: bool,
+
+    /// Bnnn jumps to nnn + Vx, with x the top nibble of nnn, instead of nnn + V0
+    #[arg(long)]
+    jump_with_offset_uses_vx: bool,
+
+    /// Clip sprites at the screen edges instead of wrapping them around
+    #[arg(long)]
+    clip_sprites: bool,
+
+    /// 8xy1/8xy2/8xy3 reset VF to 0
+    #[arg(long)]
+    vf_reset_on_logic: bool,
+
+    /// Dxyn waits for the next 60Hz frame, so at most one sprite is drawn per frame
+    #[arg(long)]
+    display_wait: bool,
+
+    /// Fx1E sets VF when I + Vx passes 0xFFF and wraps I to 12 bits
+    #[arg(long)]
+    index_overflow_sets_vf: bool,
+
+    /// Dxyn ORs sprites onto the screen instead of XORing them, so they never erase pixels
+    #[arg(long)]
+    draw_or: bool,
+}
+
+impl QuirkArgs {
+    /// Turns on the quirks given as flags over `quirks`, which come from --config or default to
+    /// the SUPER-CHIP behaviour.
+    fn apply(&self, quirks: Quirks) -> Quirks {
+        Quirks {
+            shift_uses_vy: quirks.shift_uses_vy || self.shift_uses_vy,
+            load_store_increments_index: quirks.load_store_increments_index
+                || self.load_store_increments_index,
+            jump_with_offset_uses_vx: quirks.jump_with_offset_uses_vx
+                || self.jump_with_offset_uses_vx,
+            display_wraps: quirks.display_wraps && !self.clip_sprites,
+            vf_reset_on_logic: quirks.vf_reset_on_logic || self.vf_reset_on_logic,
+            display_wait: quirks.display_wait || self.display_wait,
+            index_overflow_sets_vf: quirks.index_overflow_sets_vf || self.index_overflow_sets_vf,
+            draw_mode: if self.draw_or {
+                DrawMode::Or
+            } else {
+                quirks.draw_mode
+            },
+        }
+    }
+}
+
+impl Args {
+    fn window_size(&self) -> (u32, u32) {
+        match self.scale {
+            Some(scale) => (
+                Display::VIDEO_WIDTH as u32 * scale,
+                Display::VIDEO_HEIGHT as u32 * scale,
+            ),
+            None => (800, 600),
+        }
+    }
+
+    fn instructions_per_frame(&self) -> u32 {
+        self.ipf.unwrap_or_else(|| {
+            // Approximate the old one-instruction-every-`cycle_delay` speed
+            let frame_ms = 1000.0 / Chip8::TIMER_FREQUENCY as f64;
+            ((frame_ms / self.cycle_delay.max(1) as f64).round() as u32).max(1)
+        })
+    }
+}
+
+/// The `--rom-path` that reads the ROM from stdin.
+const STDIN_PATH: &str = "-";
+
+/// Reads the ROM at `path`, or everything piped to stdin if the path is `-`, decompressing it if
+/// it's gzipped and the `gzip` feature is enabled.
+fn load_rom(path: &str) -> Result<Vec<u8>, String> {
+    let rom = if path == STDIN_PATH {
+        let mut rom = Vec::new();
+        io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|e| e.to_string())?;
+        if rom.is_empty() {
+            return Err(
+                "stdin was empty, pipe a ROM in e.g. `cat game.ch8 | chip8 -r -`".to_string(),
+            );
+        }
+        rom
+    } else {
+        fs::read(path).map_err(|e| e.to_string())?
+    };
+
+    #[cfg(feature = "gzip")]
+    let rom = chip8::decompress_rom(rom).map_err(|e| e.to_string())?;
+    Ok(rom)
+}
+
+fn load_font(path: &Path) -> Result<[u8; 80], String> {
+    let font = fs::read(path).map_err(|e| e.to_string())?;
+
+    <[u8; 80]>::try_from(font.as_slice())
+        .map_err(|_| format!("expected 80 bytes but the file is {} bytes", font.len()))
+}
+
+/// A nonzero seed that differs between runs. The generator has a single cycle through every
+/// nonzero state, so this only picks where in the sequence a game starts.
+fn clock_seed() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    ((nanos ^ (nanos >> 16) ^ (nanos >> 32)) as u16).max(1)
+}
+
+fn main() -> ExitCode {
     env_logger::init();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    #[cfg(feature = "serde")]
+    let quirks = match &args.config.clone() {
+        Some(path) => match config::load(path) {
+            Ok(config) => config.apply(&mut args, &matches),
+            Err(e) => {
+                eprintln!("Failed to load config {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Quirks::default(),
+    };
+    #[cfg(not(feature = "serde"))]
+    let quirks = Quirks::default();
+    let quirks = args.quirks.apply(quirks);
+
+    #[cfg(feature = "serde")]
+    if let Some(path) = &args.save_config {
+        if let Err(e) = config::save(path, config::Config::from_args(&args, quirks)) {
+            eprintln!("Failed to save config {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+        println!("Saved settings to {}", path.display());
+        if args.rom_path.is_none() && args.assemble.is_none() {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    if let (Some(source), Some(output)) = (&args.assemble, &args.output) {
+        return assemble_file(source, output);
+    }
+    let rom_path = args
+        .rom_path
+        .clone()
+        .expect("clap requires a ROM unless assembling or saving a config");
+
+    #[cfg(feature = "watch")]
+    if args.watch && rom_path == STDIN_PATH {
+        eprintln!("A ROM read from stdin can't be watched for changes");
+        return ExitCode::FAILURE;
+    }
+    let rom = match load_rom(&rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to load ROM {}: {}", rom_path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.disassemble {
+        for (address, opcode, mnemonic) in chip8::disassemble(&rom) {
+            println!("{}", trace::listing_line(address, opcode, &mnemonic));
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let mut chip8 = match Chip8::from_bytes_with_memory_size(&rom, args.memory.size()) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            eprintln!("Failed to load ROM {}: {}", rom_path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(address) = args.start_address {
+        chip8 = match chip8.with_start_address(address) {
+            Ok(chip8) => chip8,
+            Err(e) => {
+                eprintln!("Failed to load ROM {}: {}", rom_path, e);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    chip8 = chip8
+        .with_quirks(quirks)
+        .with_unknown_opcode_policy(args.on_unknown.into());
+    if let Some(path) = &args.font {
+        match load_font(path) {
+            Ok(font) => chip8 = chip8.with_fontset(&font),
+            Err(e) => {
+                eprintln!("Failed to load font {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if (args.record_input.is_some() || args.play_input.is_some()) && args.backend == Backend::Tui {
+        eprintln!("Input can only be recorded and played back in the SDL window");
+        return ExitCode::FAILURE;
+    }
+    // Played back input only reproduces the session with the seed and speed it was recorded at
+    let playback = match &args.play_input {
+        Some(path) => match InputPlayback::load(path) {
+            Ok((session, playback)) => {
+                args.ipf = Some(session.instructions_per_frame);
+                args.rng = session.rng;
+                Some((session.seed, playback))
+            }
+            Err(e) => {
+                eprintln!("Failed to load input recording {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let seed = match &playback {
+        Some((seed, _)) => *seed,
+        None => args
+            .seed
+            .or_else(|| (!args.deterministic && !args.headless).then(clock_seed)),
+    };
+    match (args.rng, seed) {
+        (Generator::Lfsr, Some(seed)) => chip8 = chip8.with_seed(seed),
+        (Generator::Lfsr, None) => {}
+        (Generator::Xorshift, seed) => {
+            let seed = seed.unwrap_or(XorShift::DEFAULT_SEED);
+            chip8 = chip8.with_rng(Box::new(XorShift::new(seed)));
+        }
+    }
+    if args.no_rng {
+        chip8 = chip8.without_randomness();
+    }
+    if args.profile_opcodes {
+        chip8 = chip8.with_opcode_profile();
+    }
+    let input_log = match (&args.record_input, playback) {
+        (Some(path), _) => {
+            let session = Session {
+                rng: args.rng,
+                seed,
+                instructions_per_frame: args.instructions_per_frame(),
+            };
+            match InputRecorder::create(path, session) {
+                Ok(recorder) => Some(InputLog::Record(recorder)),
+                Err(e) => {
+                    eprintln!("Failed to create input recording {}: {}", path.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        (None, Some((_, playback))) => Some(InputLog::Play(playback)),
+        (None, None) => None,
+    };
+    if let Some(path) = &args.flags_file {
+        match fs::read(path) {
+            Ok(flags) => chip8.set_rpl_flags(&flags),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("Failed to read flags file {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut tracer = match &args.trace {
+        Some(path) => match Tracer::create(path) {
+            Ok(tracer) => Some(tracer),
+            Err(e) => {
+                eprintln!("Failed to create trace file {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let flags_file = args.flags_file.clone();
+
+    let (chip8, exit_code) = if args.headless {
+        let length = match args.exit_after_cycles {
+            Some(cycles) => RunLength::Cycles(cycles),
+            None => RunLength::Frames(args.frames),
+        };
+        let exit_code = headless::run_headless(
+            &mut chip8,
+            length,
+            args.instructions_per_frame(),
+            &args.expected_registers,
+            &mut tracer,
+        );
+        (chip8, exit_code)
+    } else {
+        let chip8 = match args.backend {
+            Backend::Tui => match tui::run_tui(
+                chip8,
+                args.instructions_per_frame(),
+                args.layout.char_map(),
+                args.mute,
+                &mut tracer,
+            ) {
+                Ok(chip8) => chip8,
+                Err(e) => {
+                    eprintln!("Terminal error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            },
+            Backend::Sdl => match run_window(args, &rom_path, chip8, input_log, &mut tracer) {
+                Ok(chip8) => chip8,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            },
+        };
+        save_flags(flags_file.as_deref(), &chip8);
+        (chip8, ExitCode::SUCCESS)
+    };
+
+    if let Some(counts) = chip8.opcode_profile() {
+        print_opcode_profile(counts);
+    }
+
+    if let Some(tracer) = tracer {
+        if let Err(e) = tracer.finish() {
+            eprintln!("Failed to write trace: {}", e);
+        }
+    }
+
+    exit_code
+}
+
+/// Lists the instructions that ran, most frequent first.
+fn print_opcode_profile(counts: &[u64; Instruction::KINDS]) {
+    let total: u64 = counts.iter().sum();
+    let mut kinds: Vec<_> = (0..Instruction::KINDS).filter(|&k| counts[k] > 0).collect();
+    kinds.sort_by_key(|&k| std::cmp::Reverse(counts[k]));
+
+    println!("Opcode profile, {} instructions:", total);
+    for kind in kinds {
+        println!(
+            "  {}  {:>12}  {:5.1}%",
+            Instruction::PATTERNS[kind],
+            counts[kind],
+            counts[kind] as f64 * 100.0 / total as f64
+        );
+    }
+}
+
+fn assemble_file(source: &Path, output: &Path) -> ExitCode {
+    let rom = fs::read_to_string(source)
+        .map_err(|e| e.to_string())
+        .and_then(|text| chip8::assemble(&text).map_err(|e| e.to_string()));
+
+    match rom.and_then(|rom| fs::write(output, rom).map_err(|e| e.to_string())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to assemble {}: {}", source.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_window(
+    args: Args,
+    rom_path: &str,
+    chip8: Chip8,
+    input_log: Option<InputLog>,
+    tracer: &mut Option<Tracer>,
+) -> Result<Chip8, String> {
     let sdl_context = sdl2::init().unwrap();
 
-    let args = Args::parse();
+    let keymap = match &args.keymap {
+        Some(path) => keymap::load_keymap(path)
+            .map_err(|e| format!("Failed to load keymap {}: {}", path.display(), e))?,
+        None => args.layout.keymap(),
+    };
+
+    let controller_map = match &args.controller_map {
+        Some(path) => keymap::load_controller_map(path)
+            .map_err(|e| format!("Failed to load controller map {}: {}", path.display(), e))?,
+        None => keymap::default_controller_map(),
+    };
+
+    let mut palette = args.palette.colors();
+    palette[0] = args.bg.unwrap_or(palette[0]);
+    palette[1] = args.fg.unwrap_or(palette[1]);
+
+    // A piped ROM has no file to name the window after or keep save states next to
+    let rom_path = if rom_path == STDIN_PATH {
+        "stdin"
+    } else {
+        rom_path
+    };
+    let rom_name = Path::new(rom_path).file_stem().map_or_else(
+        || rom_path.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+
+    #[cfg(feature = "watch")]
+    let watcher = args
+        .watch
+        .then(|| {
+            watch::RomWatcher::new(Path::new(rom_path))
+                .map_err(|e| eprintln!("Failed to watch {}: {}", rom_path, e))
+                .ok()
+        })
+        .flatten();
+
+    let options = RunOptions {
+        rom_name,
+        input_log,
+        #[cfg(feature = "watch")]
+        watcher,
+        #[cfg(feature = "serde")]
+        rom_path: PathBuf::from(rom_path),
+        instructions_per_frame: args.instructions_per_frame(),
+        window_size: args.window_size(),
+        turbo_factor: args.turbo_factor,
+        speed_cpu_only: args.speed_cpu_only,
+        rewind_frames: args.rewind_frames,
+        start_paused: args.debug,
+        pause_on_blur: !args.no_pause_on_blur,
+        breakpoints: args.breakpoints,
+        palette,
+        stretch: args.stretch,
+        fade: args.fade,
+        crt: args.crt,
+        grid: args.grid.then_some(args.grid_color),
+        capture_dir: args.capture_dir,
+        capture_scale: args.capture_scale as usize,
+        record: args.record,
+        record_fps: args.record_fps,
+        keymap,
+        controller_map,
+        stats: args.stats,
+        show_fps: args.show_fps,
+        mute: args.mute,
+        volume: args.volume,
+        waveform: args.waveform,
+        frequency: args.frequency as f32,
+    };
+
+    Ok(run_chip8(sdl_context, chip8, options, tracer))
+}
 
-    let chip8 = Chip8::read_rom(&args.rom_path).unwrap();
-    run_chip8(sdl_context, chip8, args.cycle_delay);
+fn save_flags(path: Option<&Path>, chip8: &Chip8) {
+    if let Some(path) = path {
+        if let Err(e) = fs::write(path, chip8.rpl_flags()) {
+            eprintln!("Failed to save flags file {}: {}", path.display(), e);
+        }
+    }
 }
@@ -0,0 +1,71 @@
+use sdl2::rect::Rect;
+
+/// Width and height of a glyph in font pixels, not counting the 1 pixel gap after it.
+const GLYPH_SIZE: (i32, i32) = (3, 5);
+
+/// 3x5 glyphs for the characters the overlay needs, one row per byte with the leftmost pixel in
+/// bit 2. Anything else is drawn as a space.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        _ => [0; 5],
+    }
+}
+
+/// Size of `text` in window pixels when drawn at `scale`.
+pub(crate) fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let chars = text.chars().count() as u32;
+    let width = (chars * (GLYPH_SIZE.0 as u32 + 1)).saturating_sub(1);
+    (width * scale, GLYPH_SIZE.1 as u32 * scale)
+}
+
+/// The squares making up `text` drawn at `(x, y)`, each font pixel `scale` window pixels wide.
+pub(crate) fn text_rects(text: &str, (x, y): (i32, i32), scale: u32) -> Vec<Rect> {
+    let step = scale as i32;
+    let mut rects = Vec::new();
+    for (n, c) in text.chars().enumerate() {
+        let left = x + n as i32 * (GLYPH_SIZE.0 + 1) * step;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_SIZE.0 {
+                if bits & (0b100 >> col) != 0 {
+                    rects.push(Rect::new(
+                        left + col * step,
+                        y + row as i32 * step,
+                        scale,
+                        scale,
+                    ));
+                }
+            }
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_glyphs_side_by_side() {
+        assert_eq!(text_size("60 FPS", 2), (46, 10));
+
+        let rects = text_rects("1 1", (10, 20), 2);
+        assert_eq!(rects.len(), 16);
+        assert_eq!(rects[0], Rect::new(12, 20, 2, 2));
+        // The second 1 starts two glyphs and their gaps along
+        assert_eq!(rects[8], Rect::new(28, 20, 2, 2));
+    }
+}
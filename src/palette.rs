@@ -0,0 +1,84 @@
+use sdl2::pixels::Color;
+
+/// Built-in colour schemes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Palette {
+    /// White on black
+    #[default]
+    Grayscale,
+    /// Greens of an original Game Boy screen
+    Lcd,
+    /// Amber phosphor monitor
+    Amber,
+    /// Green phosphor monitor
+    Green,
+    /// Commodore 64 start-up blues
+    C64,
+}
+
+impl Palette {
+    /// Colours for each combination of lit XO-CHIP planes: off, plane 1, plane 2 and both planes.
+    pub(crate) fn colors(self) -> [Color; 4] {
+        match self {
+            Palette::Grayscale => [
+                Color::RGB(0x00, 0x00, 0x00),
+                Color::RGB(0xFF, 0xFF, 0xFF),
+                Color::RGB(0xAA, 0xAA, 0xAA),
+                Color::RGB(0x55, 0x55, 0x55),
+            ],
+            Palette::Lcd => [
+                Color::RGB(0x9B, 0xBC, 0x0F),
+                Color::RGB(0x0F, 0x38, 0x0F),
+                Color::RGB(0x8B, 0xAC, 0x0F),
+                Color::RGB(0x30, 0x62, 0x30),
+            ],
+            Palette::Amber => [
+                Color::RGB(0x1A, 0x10, 0x00),
+                Color::RGB(0xFF, 0xB0, 0x00),
+                Color::RGB(0xB3, 0x7B, 0x00),
+                Color::RGB(0x66, 0x46, 0x00),
+            ],
+            Palette::Green => [
+                Color::RGB(0x00, 0x14, 0x00),
+                Color::RGB(0x33, 0xFF, 0x33),
+                Color::RGB(0x20, 0xA0, 0x20),
+                Color::RGB(0x10, 0x60, 0x10),
+            ],
+            Palette::C64 => [
+                Color::RGB(0x40, 0x31, 0x8D),
+                Color::RGB(0x78, 0x69, 0xC4),
+                Color::RGB(0x9A, 0xD2, 0x84),
+                Color::RGB(0xB8, 0xC7, 0x6F),
+            ],
+        }
+    }
+}
+
+/// Parses a `#RRGGBB` colour, for use as a clap value parser.
+pub(crate) fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or("expected a colour in #RRGGBB form, e.g. #FFB000")?;
+    let rgb = u32::from_str_radix(hex, 16).unwrap();
+
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(parse_color("#FFB000"), Ok(Color::RGB(0xFF, 0xB0, 0x00)));
+        assert_eq!(parse_color("#0a1b2c"), Ok(Color::RGB(0x0A, 0x1B, 0x2C)));
+    }
+
+    #[test]
+    fn rejects_malformed_colours() {
+        for s in ["FFB000", "#FFB00", "#FFB0000", "#GGGGGG", "#+FFFFF"] {
+            assert!(parse_color(s).is_err(), "{}", s);
+        }
+    }
+}
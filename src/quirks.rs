@@ -0,0 +1,49 @@
+use crate::display::DrawMode;
+
+/// Behaviours that differ between CHIP-8 interpreters. The defaults match the SUPER-CHIP
+/// interpretation this emulator has always used; the COSMAC VIP behaviour many classic ROMs expect
+/// can be enabled per quirk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields, rename_all = "kebab-case")
+)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift `Vy` and store the result in `Vx`, rather than shifting `Vx` in place
+    pub shift_uses_vy: bool,
+    /// `Fx55`/`Fx65` leave `I` incremented by `x + 1`
+    pub load_store_increments_index: bool,
+    /// `Bnnn` is read as `Bxnn` and jumps to `xnn + Vx` instead of `nnn + V0`
+    pub jump_with_offset_uses_vx: bool,
+    /// Sprites wrap around the screen edges instead of being clipped
+    pub display_wraps: bool,
+    /// `8xy1`/`8xy2`/`8xy3` reset `VF` to 0
+    pub vf_reset_on_logic: bool,
+    /// `Dxyn` waits for the next 60Hz vertical blank, so at most one sprite is drawn per frame.
+    /// [`Chip8::cycle`](crate::Chip8::cycle) flags the wait through
+    /// [`Chip8::is_waiting_for_vblank`](crate::Chip8::is_waiting_for_vblank) and the run loop
+    /// stops executing instructions until the frame is over.
+    pub display_wait: bool,
+    /// `Fx1E` sets `VF` to 1 when `I + Vx` passes 0xFFF, and 0 otherwise, then wraps `I` to 12
+    /// bits. The Amiga interpreter behaved like this and at least one game, Spacefight 2091!,
+    /// relies on it.
+    pub index_overflow_sets_vf: bool,
+    /// How `Dxyn` combines sprites with the screen. Every original interpreter XORs them.
+    pub draw_mode: DrawMode,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_index: false,
+            jump_with_offset_uses_vx: false,
+            display_wraps: true,
+            vf_reset_on_logic: false,
+            display_wait: false,
+            index_overflow_sets_vf: false,
+            draw_mode: DrawMode::Xor,
+        }
+    }
+}
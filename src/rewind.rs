@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+
+use chip8::Chip8;
+
+/// Bounded history of full machine snapshots, oldest first.
+pub(crate) struct Rewind {
+    states: VecDeque<Chip8>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(chip8.clone());
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Chip8> {
+        self.states.pop_back()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.states.clear();
+    }
+}
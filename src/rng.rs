@@ -0,0 +1,106 @@
+use std::fmt::Debug;
+
+/// Source of the random bytes `Cxkk` masks, so generators other than the default LFSR can be
+/// plugged in with [`Chip8::with_rng`](crate::Chip8::with_rng).
+pub trait Rng: RngClone + Debug + Send {
+    fn next_byte(&mut self) -> u8;
+
+    /// The generator's position, saved in snapshots so a restored machine draws the same
+    /// numbers. Generators that can't be saved leave this as 0.
+    fn state(&self) -> u64 {
+        0
+    }
+
+    /// Moves the generator to a position returned by [`Rng::state`].
+    fn set_state(&mut self, _state: u64) {}
+}
+
+/// Lets a boxed [`Rng`] be cloned along with the machine holding it. Implemented for every
+/// `Clone` generator.
+pub trait RngClone {
+    fn clone_box(&self) -> Box<dyn Rng>;
+}
+
+impl<T: Rng + Clone + 'static> RngClone for T {
+    fn clone_box(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Rng> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Marsaglia's 32-bit xorshift generator. Unlike the 16-bit LFSR, whose bytes come from eight
+/// consecutive shifts of one register and so follow on from each other, its output has no visible
+/// patterns and only repeats after 2^32 - 1 numbers. No original interpreter used it.
+#[derive(Clone, Debug)]
+pub struct XorShift(u32);
+
+impl XorShift {
+    /// Seed used when none is given, e.g. in headless runs.
+    pub const DEFAULT_SEED: u16 = 0x1234;
+
+    /// Spreads a 16-bit seed over the 32-bit state. Multiplying by an odd constant maps every
+    /// nonzero seed to a nonzero state; 0 is treated as 1.
+    pub fn new(seed: u16) -> Self {
+        Self((seed.max(1) as u32).wrapping_mul(0x9E37_79B9))
+    }
+}
+
+impl Rng for XorShift {
+    fn next_byte(&mut self) -> u8 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+
+        // The high bits are the best mixed
+        (x >> 24) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.0 = (state as u32).max(1);
+    }
+}
+
+/// Always returns all ones, so `Cxkk` loads `kk` itself.
+#[derive(Clone, Debug)]
+pub(crate) struct AllOnes;
+
+impl Rng for AllOnes {
+    fn next_byte(&mut self) -> u8 {
+        0xFF
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xorshift_bytes_are_evenly_spread() {
+        let mut rng = XorShift::new(0x1234);
+        let mut counts = [0u32; 256];
+        for _ in 0..256 * 256 {
+            counts[rng.next_byte() as usize] += 1;
+        }
+
+        // Each value is expected 256 times; a fair generator stays well within a quarter of that
+        for (byte, &count) in counts.iter().enumerate() {
+            assert!(
+                (192..=320).contains(&count),
+                "{:#04x} drawn {} times",
+                byte,
+                count
+            );
+        }
+    }
+}
@@ -1,114 +1,476 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-use log::debug;
-use sdl2::event::Event;
+use log::{debug, warn};
+use sdl2::controller::Button;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+#[cfg(feature = "serde")]
+use sdl2::keyboard::Mod;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use std::mem::MaybeUninit;
-
-use crate::chip8;
-use crate::display::Display;
-
-#[inline(always)]
-fn keycode_to_idx(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::Num4 => Some(0xC),
-        Keycode::R => Some(0xD),
-        Keycode::F => Some(0xE),
-        Keycode::V => Some(0xF),
-        _ => None,
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use chip8::{Chip8, Display};
+
+use crate::audio::{Beeper, Waveform};
+use crate::capture::{self, Recorder};
+use crate::debugger::Debugger;
+use crate::overlay;
+use crate::rewind::Rewind;
+#[cfg(feature = "serde")]
+use crate::snapshot;
+use crate::tas::InputLog;
+use crate::trace::Tracer;
+#[cfg(feature = "watch")]
+use crate::watch::RomWatcher;
+
+/// Keeps pixels glowing for a few frames after they turn off, like the slow phosphor of old CRTs.
+/// This hides the flicker of games that erase and redraw their sprites every frame.
+#[derive(Default)]
+struct Phosphor {
+    /// The value each pixel was last lit with, and how many frames it has left to glow
+    glow: Vec<(u32, u32)>,
+    fading: bool,
+}
+
+impl Phosphor {
+    const FADE_FRAMES: u32 = 4;
+
+    fn step(&mut self, video: &[u32]) {
+        if self.glow.len() != video.len() {
+            self.glow = vec![(0, 0); video.len()];
+        }
+
+        self.fading = false;
+        for (glow, &pixel) in self.glow.iter_mut().zip(video) {
+            if pixel != 0 {
+                *glow = (pixel, Self::FADE_FRAMES);
+            } else if glow.1 > 0 {
+                glow.1 -= 1;
+                self.fading = true;
+            }
+        }
+    }
+
+    fn color(&self, index: usize, pixel: u32, palette: &[Color; 4]) -> Color {
+        match self.glow.get(index) {
+            Some(&(lit, frames)) if pixel == 0 && frames > 0 => {
+                let t = frames as f32 / (Self::FADE_FRAMES + 1) as f32;
+                let (from, to) = (palette[0], palette[lit as usize]);
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                Color::RGB(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+            }
+            _ => palette[pixel as usize],
+        }
     }
 }
 
 pub(crate) struct Screen<'a> {
     canvas: &'a mut Canvas<Window>,
-    rects: [Rect; Display::SIZE],
+    texture_creator: &'a TextureCreator<WindowContext>,
+    /// Holds one texel per CHIP-8 pixel and is scaled up when copied to the window
+    texture: Texture<'a>,
+    /// RGBA staging buffer the video is converted into before uploading it to the texture
+    pixels: Vec<u8>,
+    /// Area of the window the texture is drawn into
+    dest: Rect,
+    resolution: (usize, usize),
+    palette: [Color; 4],
+    /// Fill the whole window rather than keeping pixels square
+    stretch: bool,
+    phosphor: Option<Phosphor>,
+    /// Darken scanlines and add a slight glow, like a CRT
+    crt: bool,
+    /// Colour of the lines drawn between pixels, if any
+    grid: Option<Color>,
+    /// Text drawn over the top left corner of the display, e.g. the frame rate
+    overlay: Option<String>,
 }
 
 impl<'a> Screen<'a> {
-    const DISPLAY_ON_PIXEL: Color = Color::RGB(255, 255, 255);
-    const DISPLAY_OFF_PIXEL: Color = Color::RGB(0, 0, 0);
-
-    pub(crate) fn new(canvas: &'a mut Canvas<Window>) -> Self {
-        let (pixel_size_x, pixel_size_y) = Self::pixel_size(canvas);
-        let rects = {
-            // Safety:
-            // `assume_init` is safe here because the type we are claiming to have initialised here is a
-            // bunch of `MaybeUninit`s, which do not require initialisation
-            let mut rects: [MaybeUninit<Rect>; Display::SIZE] =
-                unsafe { MaybeUninit::uninit().assume_init() };
-
-            for (i, item) in rects.iter_mut().enumerate() {
-                *item = MaybeUninit::new(Rect::from_center(
-                    (
-                        ((pixel_size_x * (i % Display::VIDEO_WIDTH) as u32) + pixel_size_x / 2)
-                            as i32,
-                        ((pixel_size_y * (i / Display::VIDEO_WIDTH) as u32) + pixel_size_y / 2)
-                            as i32,
-                    ),
-                    pixel_size_x,
-                    pixel_size_y,
-                ));
-            }
-            // Safety:
-            // Everything is now initialised. Transmute the array to the initialised type.
-            unsafe { std::mem::transmute::<_, [Rect; Display::SIZE]>(rects) }
+    pub(crate) fn new(
+        canvas: &'a mut Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        palette: [Color; 4],
+        stretch: bool,
+        fade: bool,
+        crt: bool,
+        grid: Option<Color>,
+    ) -> Self {
+        let resolution = (Display::VIDEO_WIDTH, Display::VIDEO_HEIGHT);
+        let texture = Self::create_texture(texture_creator, resolution);
+
+        let mut screen = Self {
+            canvas,
+            texture_creator,
+            texture,
+            pixels: Vec::new(),
+            dest: Rect::new(0, 0, 1, 1),
+            resolution,
+            palette,
+            stretch,
+            phosphor: fade.then(Phosphor::default),
+            crt,
+            grid,
+            overlay: None,
         };
-        Self { canvas, rects }
+        screen.recompute_layout();
+
+        screen
     }
 
-    #[inline(always)]
-    fn pixel_size(canvas: &Canvas<Window>) -> (u32, u32) {
-        let (window_width, window_height) = canvas.window().size();
-        (
-            (window_width as usize / Display::VIDEO_WIDTH) as u32,
-            (window_height as usize / Display::VIDEO_HEIGHT) as u32,
-        )
+    fn create_texture(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        (width, height): (usize, usize),
+    ) -> Texture<'a> {
+        texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+            .unwrap()
+    }
+
+    /// Fits the display into the window again, after the window or the emulated resolution
+    /// changed size. Unless stretching, pixels are scaled by the largest whole number that fits
+    /// and the image is centred between black bars.
+    pub(crate) fn recompute_layout(&mut self) {
+        let (width, height) = self.resolution;
+        let (window_width, window_height) = self.canvas.window().size();
+        let (pixel_width, pixel_height) =
+            Self::pixel_size((window_width, window_height), width, height, self.stretch);
+        let (dest_width, dest_height) = (pixel_width * width as u32, pixel_height * height as u32);
+
+        self.dest = Rect::new(
+            (window_width.saturating_sub(dest_width) / 2) as i32,
+            (window_height.saturating_sub(dest_height) / 2) as i32,
+            dest_width,
+            dest_height,
+        );
+    }
+
+    fn rgba(&self, index: usize, pixel: u32) -> [u8; 4] {
+        let color = match &self.phosphor {
+            Some(phosphor) => phosphor.color(index, pixel, &self.palette),
+            None => self.palette[pixel as usize],
+        };
+        [color.r, color.g, color.b, 0xFF]
+    }
+
+    /// Advances the phosphor fade by one 60Hz frame, if enabled.
+    pub(crate) fn fade_step(&mut self, video: &[u32]) {
+        if let Some(phosphor) = &mut self.phosphor {
+            phosphor.step(video);
+        }
     }
 
-    pub(crate) fn update_from_video(&mut self, video: &[u32; Display::SIZE]) {
-        debug_assert_eq!(video.len(), self.rects.len());
+    /// Whether pixels are still fading out, so the screen needs repainting even though the video
+    /// hasn't changed.
+    pub(crate) fn is_fading(&self) -> bool {
+        self.phosphor.as_ref().is_some_and(|p| p.fading)
+    }
+
+    pub(crate) fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.canvas.window_mut().set_title(title) {
+            warn!("Failed to set the window title: {}", e);
+        }
+    }
+
+    fn pixel_size(
+        (window_width, window_height): (u32, u32),
+        width: usize,
+        height: usize,
+        stretch: bool,
+    ) -> (u32, u32) {
+        let scale_x = (window_width / width as u32).max(1);
+        let scale_y = (window_height / height as u32).max(1);
+
+        if stretch {
+            (scale_x, scale_y)
+        } else {
+            let scale = scale_x.min(scale_y);
+            (scale, scale)
+        }
+    }
 
+    /// Presents the video buffer, only converting the `changed` pixels when given. Pass `None`
+    /// to repaint everything.
+    pub(crate) fn update_from_video(
+        &mut self,
+        video: &[u32],
+        resolution: (usize, usize),
+        changed: Option<&[usize]>,
+    ) {
+        let mut changed = changed;
+        if resolution != self.resolution {
+            self.resolution = resolution;
+            self.texture = Self::create_texture(self.texture_creator, resolution);
+            self.recompute_layout();
+            changed = None;
+        }
+        debug_assert_eq!(video.len(), resolution.0 * resolution.1);
+
+        // Fading pixels change colour without the video changing, so they need a full repaint
+        match changed {
+            Some(changed) if self.pixels.len() == video.len() * 4 && self.phosphor.is_none() => {
+                for &i in changed {
+                    let rgba = self.rgba(i, video[i]);
+                    self.pixels[i * 4..i * 4 + 4].copy_from_slice(&rgba);
+                }
+            }
+            _ => {
+                self.pixels.clear();
+                for (i, pixel) in video.iter().enumerate() {
+                    let rgba = self.rgba(i, *pixel);
+                    self.pixels.extend_from_slice(&rgba);
+                }
+            }
+        }
+        self.texture
+            .update(None, &self.pixels, resolution.0 * 4)
+            .unwrap();
+
+        self.present();
+    }
+
+    /// Sets the text drawn over the display, or removes it. It appears at the next `present`.
+    pub(crate) fn set_overlay(&mut self, text: Option<String>) {
+        self.overlay = text;
+    }
+
+    /// Draws the last uploaded video and the overlay to the window.
+    pub(crate) fn present(&mut self) {
+        self.canvas.set_draw_color(Color::BLACK);
         self.canvas.clear();
+        self.canvas.copy(&self.texture, None, self.dest).unwrap();
+        if self.crt {
+            self.draw_crt_effect();
+        }
+        if let Some(color) = self.grid {
+            self.canvas.set_draw_color(color);
+            if let Err(e) = self
+                .canvas
+                .fill_rects(&grid_lines(self.dest, self.resolution))
+            {
+                warn!("Failed to draw the grid: {}", e);
+            }
+        }
 
-        for (pixel, rect) in video.iter().zip(self.rects.iter()) {
-            if *pixel == 0 {
-                self.canvas.set_draw_color(Self::DISPLAY_OFF_PIXEL)
-            } else if *pixel == 1 {
-                self.canvas.set_draw_color(Self::DISPLAY_ON_PIXEL)
-            } else {
-                unreachable!("Unknown pixel colour")
+        if let Some(text) = &self.overlay {
+            // Keep the text a readable size relative to the display, on a backing box
+            let scale = (self.dest.height() / 128).max(1);
+            let origin = (
+                self.dest.x() + 2 * scale as i32,
+                self.dest.y() + 2 * scale as i32,
+            );
+            let (width, height) = overlay::text_size(text, scale);
+            self.canvas.set_draw_color(Color::BLACK);
+            if let Err(e) = self.canvas.fill_rect(Rect::new(
+                origin.0 - scale as i32,
+                origin.1 - scale as i32,
+                width + 2 * scale,
+                height + 2 * scale,
+            )) {
+                warn!("Failed to draw the overlay: {}", e);
+            }
+            self.canvas.set_draw_color(self.palette[1]);
+            if let Err(e) = self
+                .canvas
+                .fill_rects(&overlay::text_rects(text, origin, scale))
+            {
+                warn!("Failed to draw the overlay: {}", e);
             }
-            self.canvas.fill_rect(*rect).unwrap();
         }
 
         self.canvas.present();
     }
+
+    /// Blooms lit pixels into their neighbours by adding a faint, slightly enlarged copy of the
+    /// display over it, then darkens the bottom of every pixel row into a scanline. The lines
+    /// follow the scaled pixel rows and the glow spreads at most half a pixel past the display,
+    /// so the layout is unaffected.
+    fn draw_crt_effect(&mut self) {
+        let (pixel_width, pixel_height) = (
+            self.dest.width() / self.resolution.0 as u32,
+            self.dest.height() / self.resolution.1 as u32,
+        );
+
+        let spread = (pixel_width.min(pixel_height) / 2) as i32;
+        let glow = Rect::new(
+            self.dest.x() - spread,
+            self.dest.y() - spread,
+            self.dest.width() + 2 * spread as u32,
+            self.dest.height() + 2 * spread as u32,
+        );
+        let blend_mode = self.texture.blend_mode();
+        self.texture.set_blend_mode(BlendMode::Add);
+        self.texture.set_alpha_mod(CRT_GLOW);
+        let copied = self.canvas.copy(&self.texture, None, glow);
+        self.texture.set_blend_mode(blend_mode);
+        self.texture.set_alpha_mod(0xFF);
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas
+            .set_draw_color(Color::RGBA(0, 0, 0, CRT_SCANLINE_SHADE));
+        let shaded = self
+            .canvas
+            .fill_rects(&scanline_rects(self.dest, self.resolution.1));
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        if let Err(e) = copied.and(shaded) {
+            warn!("Failed to draw the CRT effect: {}", e);
+        }
+    }
+}
+
+/// One window pixel wide lines along the borders between the pixels scaled into `dest`, for
+/// `--grid`. Empty when pixels are under 3 window pixels across, where the grid would hide them.
+fn grid_lines(dest: Rect, (width, height): (usize, usize)) -> Vec<Rect> {
+    let (pixel_width, pixel_height) = (dest.width() / width as u32, dest.height() / height as u32);
+    if pixel_width.min(pixel_height) < 3 {
+        return Vec::new();
+    }
+
+    let columns = (1..width as i32).map(|column| {
+        let x = dest.x() + column * pixel_width as i32;
+        Rect::new(x, dest.y(), 1, dest.height())
+    });
+    let rows = (1..height as i32).map(|row| {
+        let y = dest.y() + row * pixel_height as i32;
+        Rect::new(dest.x(), y, dest.width(), 1)
+    });
+    columns.chain(rows).collect()
 }
 
-pub fn run_chip8(sdl_context: sdl2::Sdl, mut chip8: chip8::Chip8, cycle_delay: u32) {
+/// Strength of the `--crt` glow, as the alpha its copy of the display is added with.
+const CRT_GLOW: u8 = 0x30;
+/// How much the `--crt` scanlines darken, as the alpha of the black drawn over them.
+const CRT_SCANLINE_SHADE: u8 = 0x60;
+
+/// The bottom third of each of the `rows` pixel rows scaled into `dest`, which `--crt` darkens
+/// into scanlines. Empty when pixels are under 3 window pixels tall, leaving too little room for
+/// a gap between lines.
+fn scanline_rects(dest: Rect, rows: usize) -> Vec<Rect> {
+    let pixel_height = dest.height() / rows as u32;
+    if pixel_height < 3 {
+        return Vec::new();
+    }
+
+    let line_height = pixel_height / 3;
+    (1..=rows as i32)
+        .map(|row| {
+            let bottom = dest.y() + row * pixel_height as i32;
+            Rect::new(
+                dest.x(),
+                bottom - line_height as i32,
+                dest.width(),
+                line_height,
+            )
+        })
+        .collect()
+}
+
+pub(crate) struct RunOptions {
+    /// Shown in the window title, usually the ROM's file name
+    pub(crate) rom_name: String,
+    /// Snapshots are saved next to the ROM, see [`snapshot::slot_path`]
+    #[cfg(feature = "serde")]
+    pub(crate) rom_path: PathBuf,
+    /// Keypad input being recorded to or played back from a file
+    pub(crate) input_log: Option<InputLog>,
+    /// Reloads the ROM when its file changes
+    #[cfg(feature = "watch")]
+    pub(crate) watcher: Option<RomWatcher>,
+    pub(crate) instructions_per_frame: u32,
+    /// Emulated frames run per real frame while fast-forwarding
+    pub(crate) turbo_factor: u32,
+    /// The - and + speed hotkeys scale the instructions per frame rather than the frame rate
+    pub(crate) speed_cpu_only: bool,
+    pub(crate) rewind_frames: usize,
+    pub(crate) start_paused: bool,
+    /// Stop running while the window doesn't have focus
+    pub(crate) pause_on_blur: bool,
+    pub(crate) breakpoints: Vec<usize>,
+    pub(crate) palette: [Color; 4],
+    pub(crate) window_size: (u32, u32),
+    pub(crate) stretch: bool,
+    pub(crate) fade: bool,
+    pub(crate) crt: bool,
+    /// Draw lines of this colour between pixels
+    pub(crate) grid: Option<Color>,
+    pub(crate) capture_dir: PathBuf,
+    pub(crate) capture_scale: usize,
+    pub(crate) record: Option<PathBuf>,
+    pub(crate) record_fps: u32,
+    pub(crate) keymap: HashMap<Keycode, usize>,
+    pub(crate) controller_map: HashMap<Button, usize>,
+    /// Print the measured instructions per second every second
+    pub(crate) stats: bool,
+    /// Draw the measured frames and instructions per second over the display
+    pub(crate) show_fps: bool,
+    /// Start with the beep silenced, toggled with M
+    pub(crate) mute: bool,
+    /// Beep volume in percent, adjusted with [ and ]
+    pub(crate) volume: u8,
+    pub(crate) waveform: Waveform,
+    /// Pitch of the beep in Hz
+    pub(crate) frequency: f32,
+}
+
+/// Applies queued keypad presses (true) and releases (false) in the order they happened, all
+/// before the frame's instructions run. A release of a key pressed earlier in the same batch waits
+/// for the next frame, along with everything queued after it, so even a tap shorter than a frame
+/// is seen as held for one whole frame. Returns the events applied.
+fn apply_key_events(chip8: &mut Chip8, events: &mut Vec<(usize, bool)>) -> Vec<(usize, bool)> {
+    let mut pressed = Vec::new();
+    let mut applied = 0;
+    for &(i, down) in events.iter() {
+        if !down && pressed.contains(&i) {
+            break;
+        }
+        if down {
+            debug!("Pressing {}", i);
+            chip8.press_key(i);
+            pressed.push(i);
+        } else {
+            debug!("Lifting {}", i);
+            chip8.lift_key(i);
+        }
+        applied += 1;
+    }
+    events.drain(..applied).collect()
+}
+
+pub(crate) fn run_chip8(
+    sdl_context: sdl2::Sdl,
+    mut chip8: Chip8,
+    options: RunOptions,
+    tracer: &mut Option<Tracer>,
+) -> Chip8 {
+    let start_recording = |path: PathBuf| {
+        Recorder::new(
+            path,
+            options.record_fps,
+            options.capture_scale,
+            &options.palette,
+        )
+        .map_err(|e| eprintln!("Failed to start recording: {}", e))
+        .ok()
+    };
+    let mut recorder = options.record.clone().and_then(start_recording);
+
     let mut event_pump = sdl_context.event_pump().unwrap();
     let mut canvas = {
         let mut canvas = sdl_context
             .video()
             .unwrap()
-            .window("chip8", 800, 600)
+            .window(
+                &window_title(&options.rom_name, &[]),
+                options.window_size.0,
+                options.window_size.1,
+            )
             .position_centered()
+            .resizable()
             .build()
             .unwrap()
             .into_canvas()
@@ -120,61 +482,643 @@ pub fn run_chip8(sdl_context: sdl2::Sdl, mut chip8: chip8::Chip8, cycle_delay: u
         canvas
     };
 
-    let cycle_delay = Duration::new(0, cycle_delay * 1_000_000); // 10 ms
-    let mut last_cycle_time = Instant::now();
-    let mut dt: Duration;
-    let mut keys_pressed = Vec::new();
-    let mut keys_up = Vec::new();
+    let mut beeper = Beeper::new(
+        &sdl_context,
+        options.volume,
+        options.waveform,
+        options.frequency,
+    )
+    .map_err(|e| warn!("Failed to open audio device, sound disabled: {}", e))
+    .ok();
 
-    let mut screen = Screen::new(&mut canvas);
+    // Controllers are opened as they're connected, including those present at startup
+    let controller_subsystem = sdl_context
+        .game_controller()
+        .map_err(|e| warn!("Failed to initialise game controllers: {}", e))
+        .ok();
+    let mut controllers = HashMap::new();
+
+    let frame_period = Duration::from_secs(1) / Chip8::TIMER_FREQUENCY;
+    let mut last_loop_time = Instant::now();
+    let mut frame_accumulator = Duration::ZERO;
+    // Keypad presses (true) and releases (false) in the order they happened
+    let mut key_events: Vec<(usize, bool)> = Vec::new();
+    let mut rewind = Rewind::new(options.rewind_frames);
+    let mut rewinding = false;
+    let mut fast_forwarding = false;
+    // Index into SPEEDS, changed with - and +
+    let mut speed = NORMAL_SPEED;
+    let mut muted = options.mute;
+    let mut volume = options.volume;
+    // Snapshot slot F6 saves to and F9 loads from, chosen with Shift and a number key
+    #[cfg(feature = "serde")]
+    let mut slot = 0;
+    let mut debugger = Debugger::new(options.start_paused, &options.breakpoints);
+    // Kept apart from the debugger's pause so regaining focus doesn't undo pausing with P
+    let mut in_background = false;
+    let mut input_log = options.input_log;
+    // Emulated frames run so far, which recorded input is timed by
+    let mut frame: u64 = 0;
+    #[cfg(feature = "watch")]
+    let mut watcher = options.watcher;
+    // When the last report was printed and the instruction count at that point
+    let mut stats = options.stats.then(|| (Instant::now(), chip8.cycle_count()));
+    // The same for the overlay, plus the emulated frames run since then
+    let mut fps = options
+        .show_fps
+        .then(|| (Instant::now(), chip8.cycle_count(), 0u32));
+
+    let texture_creator = canvas.texture_creator();
+    let mut screen = Screen::new(
+        &mut canvas,
+        &texture_creator,
+        options.palette,
+        options.stretch,
+        options.fade,
+        options.crt,
+        options.grid,
+    );
+
+    let mut title = String::new();
+    // The frame counter shown while paused
+    let mut frame_label = None;
 
     'running: loop {
-        dt = Instant::now().duration_since(last_cycle_time);
+        // Unless only the CPU is scaled, the speed hotkeys speed up or slow down emulated time,
+        // timers included, so game logic stays in step
+        let time_scale = if options.speed_cpu_only {
+            1.0
+        } else {
+            SPEEDS[speed]
+        };
+        let now = Instant::now();
+        frame_accumulator += now.duration_since(last_loop_time).mul_f32(time_scale);
+        last_loop_time = now;
 
         for event in event_pump.poll_iter() {
+            if let Some(log) = &input_log {
+                if diverges_input_log(&event, log) {
+                    continue;
+                }
+            }
+
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    screen.recompute_layout();
+                    chip8.force_redraw();
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => in_background = options.pause_on_blur,
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => in_background = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    let path = capture::timestamped_path(&options.capture_dir, "png");
+                    match capture::save_screenshot(
+                        &chip8,
+                        &path,
+                        options.capture_scale,
+                        &options.palette,
+                    ) {
+                        Ok(()) => println!("Saved screenshot to {}", path.display()),
+                        Err(e) => eprintln!("Failed to save screenshot {}: {}", path.display(), e),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => match recorder.take() {
+                    Some(r) => finish_recording(r),
+                    None => {
+                        recorder =
+                            start_recording(capture::timestamped_path(&options.capture_dir, "gif"));
+                        if let Some(r) = &recorder {
+                            println!("Recording to {}", r.path().display());
+                        }
+                    }
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    debug!("Resetting");
+                    chip8.reset();
+                }
+                #[cfg(feature = "serde")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    let path = snapshot::slot_path(&options.rom_path, slot);
+                    match snapshot::save(&path, &chip8) {
+                        Ok(()) => println!("Saved state to {}", path.display()),
+                        Err(e) => eprintln!("Failed to save state to {}: {}", path.display(), e),
+                    }
+                }
+                #[cfg(feature = "serde")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let path = snapshot::slot_path(&options.rom_path, slot);
+                    match snapshot::load(&path, &mut chip8) {
+                        Ok(()) => println!("Loaded state from {}", path.display()),
+                        Err(e) => eprintln!("Failed to load state from {}: {}", path.display(), e),
+                    }
+                }
+                #[cfg(feature = "serde")]
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+                    && snapshot::slot_key(key).is_some() =>
+                {
+                    slot = snapshot::slot_key(key).unwrap_or(slot);
+                    println!("Selected state slot {}", slot);
+                }
+                // While paused, Backspace undoes single-stepped instructions instead
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if debugger.is_paused() => debugger.request_step_back(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => fast_forwarding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => fast_forwarding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => muted = !muted,
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::LeftBracket | Keycode::RightBracket)),
+                    ..
+                } => {
+                    volume = if key == Keycode::LeftBracket {
+                        volume.saturating_sub(VOLUME_STEP)
+                    } else {
+                        (volume + VOLUME_STEP).min(100)
+                    };
+                    if let Some(beeper) = beeper.as_mut() {
+                        beeper.set_volume(volume);
+                    }
+                    println!("Volume: {}%", volume);
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            key @ (Keycode::Minus
+                            | Keycode::KpMinus
+                            | Keycode::Equals
+                            | Keycode::KpPlus),
+                        ),
+                    ..
+                } => {
+                    // Recordings are replayed at the instructions per frame they started with
+                    if options.speed_cpu_only && matches!(input_log, Some(InputLog::Record(_))) {
+                        println!("Changing the speed is off while recording input");
+                        continue;
+                    }
+                    speed = if matches!(key, Keycode::Minus | Keycode::KpMinus) {
+                        speed.saturating_sub(1)
+                    } else {
+                        (speed + 1).min(SPEEDS.len() - 1)
+                    };
+                    println!("Speed: {}x", SPEEDS[speed]);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => debugger.toggle_pause(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => debugger.request_step(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => debugger.request_frame_step(),
                 Event::KeyDown {
                     keycode: Some(key), ..
                 } => {
-                    if let Some(k) = keycode_to_idx(key) {
-                        keys_pressed.push(k);
+                    if let Some(&k) = options.keymap.get(&key) {
+                        key_events.push((k, true));
                     }
                 }
                 Event::KeyUp {
                     keycode: Some(key), ..
                 } => {
-                    if let Some(k) = keycode_to_idx(key) {
-                        keys_up.push(k);
+                    if let Some(&k) = options.keymap.get(&key) {
+                        key_events.push((k, false));
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(subsystem) = &controller_subsystem {
+                        match subsystem.open(which) {
+                            Ok(controller) => {
+                                debug!("Connected controller {}", controller.name());
+                                controllers.insert(controller.instance_id(), controller);
+                            }
+                            Err(e) => warn!("Failed to open controller {}: {}", which, e),
+                        }
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    debug!("Disconnected controller {}", which);
+                    controllers.remove(&which);
+                    // Don't leave keys stuck down if the pad goes away mid-press
+                    key_events.extend(options.controller_map.values().map(|&k| (k, false)));
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(&k) = options.controller_map.get(&button) {
+                        key_events.push((k, true));
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(&k) = options.controller_map.get(&button) {
+                        key_events.push((k, false));
                     }
                 }
                 _ => {}
             }
         }
 
-        if dt > cycle_delay {
-            last_cycle_time = Instant::now();
+        debugger.process_commands(&chip8);
 
-            for i in keys_pressed.drain(..) {
-                debug!("Pressing {}", i);
-                chip8.press_key(i);
+        #[cfg(feature = "watch")]
+        if let Some(watcher) = watcher.as_mut() {
+            match watcher.poll() {
+                Some(Ok(rom)) => match chip8.reload(&rom) {
+                    Ok(()) => {
+                        println!("Reloaded {}", watcher.path().display());
+                        // Rewinding to before the reload would mix the old ROM with the new
+                        rewind.clear();
+                    }
+                    Err(e) => eprintln!("Failed to reload {}: {}", watcher.path().display(), e),
+                },
+                Some(Err(e)) => eprintln!("Failed to reload {}: {}", watcher.path().display(), e),
+                None => {}
             }
+        }
 
-            chip8.cycle();
+        if in_background {
+            // Pick up where it left off on coming back, rather than catching up
+            frame_accumulator = Duration::ZERO;
+        }
+        // Drawing happens at most once per 60Hz frame however many instructions ran, so the
+        // display changes of a whole batch reach the window together
+        let frame_due = frame_accumulator >= frame_period;
+        // Each 60Hz frame runs a fixed batch of instructions followed by exactly one timer tick
+        while frame_accumulator >= frame_period {
+            frame_accumulator -= frame_period;
 
-            for i in keys_up.drain(..) {
-                debug!("Lifting {}", i);
-                chip8.lift_key(i);
+            if rewinding {
+                if let Some(state) = rewind.pop() {
+                    chip8 = state;
+                    chip8.force_redraw();
+                }
+                continue;
+            }
+
+            // Fast-forwarding runs several whole frames, timers included, so the game logic
+            // keeps its usual pace relative to the CPU
+            let frames = if fast_forwarding {
+                options.turbo_factor
+            } else {
+                1
+            };
+            for _ in 0..frames {
+                if let Some(InputLog::Play(playback)) = &mut input_log {
+                    playback.take(frame, &mut key_events);
+                    if playback.is_finished() {
+                        println!("Input playback finished, handing over to the keyboard");
+                        input_log = None;
+                    }
+                }
+                let applied = apply_key_events(&mut chip8, &mut key_events);
+                if let Some(InputLog::Record(recorder)) = &mut input_log {
+                    if let Err(e) = recorder.record(frame, &applied) {
+                        eprintln!("Input recording stopped: {}", e);
+                        input_log = None;
+                    }
+                }
+
+                // While paused, frames only run when stepped through one at a time
+                let stepping_frame = debugger.is_paused() && debugger.take_frame_step();
+                if debugger.is_paused() && !stepping_frame {
+                    debugger.step(&mut chip8, tracer);
+                } else {
+                    let instructions_per_frame = if options.speed_cpu_only {
+                        ((options.instructions_per_frame as f32 * SPEEDS[speed]).round() as u32)
+                            .max(1)
+                    } else {
+                        options.instructions_per_frame
+                    };
+                    for _ in 0..instructions_per_frame {
+                        if debugger.check_breakpoint(&chip8) {
+                            break;
+                        }
+                        if let Err(e) = Tracer::cycle(tracer, &mut chip8) {
+                            debugger.halt(&chip8, &e);
+                            break;
+                        }
+                        // Under the display-wait quirk a draw ends the frame's instructions
+                        if chip8.is_waiting_for_vblank() {
+                            break;
+                        }
+                    }
+                }
+
+                if !debugger.is_paused() || stepping_frame {
+                    chip8.tick_timers();
+                    frame += 1;
+                    if let Some((_, _, frames)) = fps.as_mut() {
+                        *frames += 1;
+                    }
+                    rewind.push(&chip8);
+                    screen.fade_step(chip8.get_video());
+                    if let Some(r) = recorder.as_mut() {
+                        if let Err(e) = r.capture(&chip8) {
+                            eprintln!("Recording stopped: {}", e);
+                            recorder = None;
+                        }
+                    }
+                }
             }
         }
 
-        if chip8.is_dirty() {
-            screen.update_from_video(chip8.get_video());
+        if let Some(beeper) = beeper.as_mut() {
+            beeper.set_pattern(chip8.audio_pattern(), chip8.pattern_playback_rate());
+            // The sound timer keeps counting down while muted, so games behave the same
+            beeper.set_playing(
+                chip8.is_beeping() && !debugger.is_paused() && !in_background && !muted,
+            );
+        }
+
+        // Updated a few times a second, which is often enough to follow without it jittering
+        let mut overlay_changed = false;
+        // While paused the overlay counts frames instead, for stepping through them
+        let label = debugger.is_paused().then(|| format!("Frame {}", frame));
+        if label != frame_label {
+            screen.set_overlay(label.clone());
+            overlay_changed = true;
+            frame_label = label;
+        }
+        if let Some((since, cycles, frames)) = fps.as_mut().filter(|_| frame_label.is_none()) {
+            let elapsed = since.elapsed();
+            if elapsed >= FPS_INTERVAL {
+                let secs = elapsed.as_secs_f64();
+                let executed = chip8.cycle_count().saturating_sub(*cycles);
+                screen.set_overlay(Some(format!(
+                    "{:.0} FPS {:.0} IPS",
+                    *frames as f64 / secs,
+                    executed as f64 / secs
+                )));
+                overlay_changed = true;
+                *since = Instant::now();
+                *cycles = chip8.cycle_count();
+                *frames = 0;
+            }
+        }
+
+        // In the background no frames run, but the window can still be resized
+        if (frame_due || in_background) && (chip8.is_dirty() || screen.is_fading()) {
+            screen.update_from_video(
+                chip8.get_video(),
+                chip8.resolution(),
+                chip8.changed_pixels(),
+            );
             chip8.set_clean();
+        } else if overlay_changed {
+            screen.present();
+        }
+
+        let mut status = Vec::new();
+        if debugger.is_paused() || in_background {
+            status.push("paused".to_string());
+        } else if rewinding {
+            status.push("rewinding".to_string());
+        } else if fast_forwarding {
+            status.push(format!("{}x speed", options.turbo_factor));
+        } else if speed != NORMAL_SPEED {
+            status.push(format!("{}x speed", SPEEDS[speed]));
+        }
+        if muted {
+            status.push("muted".to_string());
         }
+        let new_title = window_title(&options.rom_name, &status);
+        if new_title != title {
+            screen.set_title(&new_title);
+            title = new_title;
+        }
+
+        if let Some((since, cycles)) = stats.as_mut() {
+            let elapsed = since.elapsed();
+            if elapsed >= Duration::from_secs(1) {
+                // Rewinding and resetting can take the count backwards
+                let executed = chip8.cycle_count().saturating_sub(*cycles);
+                println!(
+                    "{:.0} instructions/s",
+                    executed as f64 / elapsed.as_secs_f64()
+                );
+                *since = Instant::now();
+                *cycles = chip8.cycle_count();
+            }
+        }
+
+        if in_background {
+            std::thread::sleep(BACKGROUND_POLL_INTERVAL);
+        } else {
+            // There's nothing to do until the next frame is due, besides handling input
+            let until_due = frame_period.saturating_sub(frame_accumulator);
+            let spent = last_loop_time.elapsed();
+            std::thread::sleep(until_due.div_f32(time_scale).saturating_sub(spent));
+        }
+    }
+
+    if let Some(r) = recorder {
+        finish_recording(r);
+    }
+    if let Some(InputLog::Record(recorder)) = input_log {
+        if let Err(e) = recorder.finish() {
+            eprintln!("Failed to save input recording: {}", e);
+        }
+    }
+
+    chip8
+}
+
+/// Whether `event` would make a session being recorded or played back come out differently, so
+/// has to be ignored. Rewinding, resetting, loading a snapshot and single-stepping aren't recorded,
+/// and playback replaces the keypad and hotkeys altogether, leaving only quitting.
+fn diverges_input_log(event: &Event, log: &InputLog) -> bool {
+    match (event, log) {
+        (
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            },
+            _,
+        ) => false,
+        (
+            Event::KeyDown { .. }
+            | Event::KeyUp { .. }
+            | Event::ControllerButtonDown { .. }
+            | Event::ControllerButtonUp { .. },
+            InputLog::Play(_),
+        ) => true,
+        (
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace | Keycode::F5 | Keycode::F9 | Keycode::Space),
+                ..
+            },
+            InputLog::Record(_),
+        ) => {
+            println!("Rewinding, resetting, loading and stepping are off while recording input");
+            true
+        }
+        _ => false,
+    }
+}
+
+/// How often events are checked for while paused in the background.
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the --show-fps overlay is refreshed.
+const FPS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Speeds - and + step through, as multiples of the normal speed.
+const SPEEDS: [f32; 7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 3;
+
+/// How far each press of [ or ] changes the volume, in percent.
+const VOLUME_STEP: u8 = 5;
+
+/// E.g. `chip8 — PONG (paused)`.
+fn window_title(rom_name: &str, status: &[String]) -> String {
+    let mut title = format!("chip8 — {}", rom_name);
+    if !status.is_empty() {
+        title.push_str(&format!(" ({})", status.join(", ")));
+    }
+    title
+}
+
+fn finish_recording(recorder: Recorder) {
+    match recorder.finish() {
+        Ok(path) => println!("Saved recording to {}", path.display()),
+        Err(e) => eprintln!("Failed to save recording: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_a_tap_for_one_frame() {
+        // SKP V0
+        let mut chip8 = Chip8::from_bytes(&[0xE0, 0x9E]).unwrap();
+        let mut events = vec![(0, true), (0, false), (3, true)];
+
+        apply_key_events(&mut chip8, &mut events);
+        assert_eq!(events, [(0, false), (3, true)]);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc(), 0x204);
+
+        apply_key_events(&mut chip8, &mut events);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn titles_window_after_rom_and_status() {
+        assert_eq!(window_title("PONG", &[]), "chip8 — PONG");
+        assert_eq!(
+            window_title("PONG", &["paused".to_string()]),
+            "chip8 — PONG (paused)"
+        );
+    }
+
+    #[test]
+    fn keeps_pixels_square_unless_stretching() {
+        assert_eq!(Screen::pixel_size((800, 600), 64, 32, false), (12, 12));
+        assert_eq!(Screen::pixel_size((800, 600), 64, 32, true), (12, 18));
+        assert_eq!(Screen::pixel_size((640, 320), 128, 64, false), (5, 5));
+    }
+
+    #[test]
+    fn scanlines_shade_the_bottom_of_each_pixel_row() {
+        let rects = scanline_rects(Rect::new(10, 20, 640, 320), 32);
+        assert_eq!(rects.len(), 32);
+        assert_eq!(rects[0], Rect::new(10, 27, 640, 3));
+        assert_eq!(rects[31], Rect::new(10, 337, 640, 3));
+
+        assert!(scanline_rects(Rect::new(0, 0, 128, 64), 32).is_empty());
+    }
+
+    #[test]
+    fn grid_lines_follow_the_scaled_pixels() {
+        let lines = grid_lines(Rect::new(10, 20, 640, 640), (64, 32));
+        assert_eq!(lines.len(), 63 + 31);
+        assert_eq!(lines[0], Rect::new(20, 20, 1, 640));
+        assert_eq!(lines[62], Rect::new(640, 20, 1, 640));
+        assert_eq!(lines[63], Rect::new(10, 40, 640, 1));
+
+        assert!(grid_lines(Rect::new(0, 0, 128, 640), (64, 32)).is_empty());
+    }
+
+    #[test]
+    fn phosphor_fades_unlit_pixels_towards_the_background() {
+        let palette = [
+            Color::RGB(0, 0, 0),
+            Color::RGB(250, 250, 250),
+            Color::RGB(0, 0, 0),
+            Color::RGB(0, 0, 0),
+        ];
+        let mut phosphor = Phosphor::default();
+
+        phosphor.step(&[1, 0]);
+        assert!(!phosphor.fading);
+        assert_eq!(phosphor.color(0, 1, &palette), palette[1]);
+        assert_eq!(phosphor.color(1, 0, &palette), palette[0]);
+
+        phosphor.step(&[0, 0]);
+        assert!(phosphor.fading);
+        assert_eq!(phosphor.color(0, 0, &palette), Color::RGB(150, 150, 150));
+
+        (0..Phosphor::FADE_FRAMES).for_each(|_| phosphor.step(&[0, 0]));
+        assert!(!phosphor.fading);
+        assert_eq!(phosphor.color(0, 0, &palette), palette[0]);
+    }
+
+    #[test]
+    fn pixels_never_shrink_to_nothing() {
+        assert_eq!(Screen::pixel_size((100, 20), 128, 64, false), (1, 1));
     }
 }
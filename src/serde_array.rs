@@ -0,0 +1,28 @@
+//! Serde helpers for fixed-size arrays longer than the 32 elements serde supports out of the box.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub(crate) fn serialize<S, T, const N: usize>(
+    array: &[T; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    array.as_slice().serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let items = Vec::<T>::deserialize(deserializer)?;
+    let len = items.len();
+
+    items
+        .try_into()
+        .map_err(|_| D::Error::invalid_length(len, &"an array of the expected length"))
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::{Chip8, Chip8Error};
+use sdl2::keyboard::Keycode;
+
+/// File a snapshot slot of the ROM at `rom_path` is kept in, next to the ROM: `PONG.state` for
+/// slot 0 and e.g. `PONG.3.state` for slot 3.
+pub(crate) fn slot_path(rom_path: &Path, slot: u8) -> PathBuf {
+    if slot == 0 {
+        rom_path.with_extension("state")
+    } else {
+        rom_path.with_extension(format!("{}.state", slot))
+    }
+}
+
+/// The slot a number key selects.
+pub(crate) fn slot_key(key: Keycode) -> Option<u8> {
+    let digit = (key as i32).checked_sub(Keycode::Num0 as i32)?;
+    (0..10).contains(&digit).then_some(digit as u8)
+}
+
+pub(crate) fn save(path: &Path, chip8: &Chip8) -> Result<(), Chip8Error> {
+    fs::write(path, chip8.save_state())?;
+    Ok(())
+}
+
+/// Restores a snapshot into `chip8`, which is left untouched if it can't be read.
+pub(crate) fn load(path: &Path, chip8: &mut Chip8) -> Result<(), Chip8Error> {
+    let state = fs::read(path)?;
+    chip8.load_state(&state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_slots_after_the_rom() {
+        let rom = Path::new("roms/PONG.ch8");
+        assert_eq!(slot_path(rom, 0), Path::new("roms/PONG.state"));
+        assert_eq!(slot_path(rom, 3), Path::new("roms/PONG.3.state"));
+
+        assert_eq!(slot_key(Keycode::Num0), Some(0));
+        assert_eq!(slot_key(Keycode::Num9), Some(9));
+        assert_eq!(slot_key(Keycode::A), None);
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let path = std::env::temp_dir().join(format!("chip8-snapshot-{}", std::process::id()));
+        // LD V0, 0x2A
+        let mut chip8 = Chip8::from_bytes(&[0x60, 0x2A]).unwrap();
+        chip8.cycle().unwrap();
+        save(&path, &chip8).unwrap();
+
+        let mut restored = Chip8::from_bytes(&[]).unwrap();
+        load(&path, &mut restored).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.registers()[0], 0x2A);
+
+        assert!(load(&path, &mut restored).is_err());
+    }
+
+    #[test]
+    fn damaged_slots_fail_to_load() {
+        let path = std::env::temp_dir().join(format!("chip8-damaged-{}", std::process::id()));
+        // CALL 0x202; JP 0x202
+        let mut chip8 = Chip8::from_bytes(&[0x22, 0x02, 0x12, 0x02]).unwrap();
+        chip8.cycle().unwrap();
+        let before = chip8.save_state();
+
+        fs::write(&path, &before[..before.len() / 2]).unwrap();
+        let result = load(&path, &mut chip8);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Chip8Error::InvalidState(_))));
+        assert_eq!(chip8.save_state(), before);
+        chip8.cycle().unwrap();
+    }
+}
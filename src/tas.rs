@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::Generator;
+
+/// What a recorded session has to be replayed with to come out the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Session {
+    pub(crate) rng: Generator,
+    /// `None` for the emulator's fixed built-in seed
+    pub(crate) seed: Option<u16>,
+    pub(crate) instructions_per_frame: u32,
+}
+
+/// Either side of an input recording, threaded through the run loop.
+pub(crate) enum InputLog {
+    Record(InputRecorder),
+    Play(InputPlayback),
+}
+
+/// Writes keypad presses and releases to a file along with the frame they were applied in.
+///
+/// The file is text: a header line such as `chip8-input rng=lfsr seed=1234 ipf=10`, then one line
+/// per event such as `120 +5` (key 5 pressed before frame 120) or `124 -5` (released).
+pub(crate) struct InputRecorder {
+    out: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub(crate) fn create(path: &Path, session: Session) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let seed = session
+            .seed
+            .map_or_else(|| "default".to_string(), |s| s.to_string());
+        let rng = session
+            .rng
+            .to_possible_value()
+            .expect("no generator is skipped");
+        writeln!(
+            out,
+            "chip8-input rng={} seed={} ipf={}",
+            rng.get_name(),
+            seed,
+            session.instructions_per_frame
+        )?;
+
+        Ok(Self { out })
+    }
+
+    pub(crate) fn record(&mut self, frame: u64, events: &[(usize, bool)]) -> io::Result<()> {
+        for &(key, down) in events {
+            writeln!(
+                self.out,
+                "{} {}{:X}",
+                frame,
+                if down { '+' } else { '-' },
+                key
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Key events read back from a file written by [`InputRecorder`], oldest first.
+pub(crate) struct InputPlayback {
+    events: VecDeque<(u64, usize, bool)>,
+}
+
+impl InputPlayback {
+    pub(crate) fn load(path: &Path) -> Result<(Session, Self), String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<(Session, Self), String> {
+        let mut lines = text.lines().enumerate();
+
+        let header = lines.next().map_or("", |(_, line)| line);
+        let fields = header
+            .strip_prefix("chip8-input ")
+            .ok_or("Not an input recording")?;
+        let mut session = Session {
+            rng: Generator::default(),
+            seed: None,
+            instructions_per_frame: 0,
+        };
+        for field in fields.split_whitespace() {
+            match field.split_once('=') {
+                Some(("rng", rng)) => {
+                    session.rng = Generator::from_str(rng, false)
+                        .map_err(|_| format!("Unknown random number generator '{}'", rng))?
+                }
+                Some(("seed", "default")) => session.seed = None,
+                Some(("seed", seed)) => {
+                    session.seed = Some(seed.parse().map_err(|_| "Invalid seed")?)
+                }
+                Some(("ipf", ipf)) => {
+                    session.instructions_per_frame =
+                        ipf.parse().map_err(|_| "Invalid instructions per frame")?
+                }
+                _ => return Err(format!("Unknown header field '{}'", field)),
+            }
+        }
+        if session.instructions_per_frame == 0 {
+            return Err("Missing instructions per frame".to_string());
+        }
+
+        let mut events = VecDeque::new();
+        for (n, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let invalid = || format!("Invalid event on line {}: '{}'", n + 1, line);
+            let (frame, event) = line.trim().split_once(' ').ok_or_else(invalid)?;
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let down = match event.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return Err(invalid()),
+            };
+            let key = usize::from_str_radix(&event[1..], 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(invalid)?;
+            events.push_back((frame, key, down));
+        }
+
+        Ok((session, Self { events }))
+    }
+
+    /// Moves the events recorded for `frame`, and any earlier ones not yet taken, into `into`.
+    pub(crate) fn take(&mut self, frame: u64, into: &mut Vec<(usize, bool)>) {
+        while let Some(&(at, key, down)) = self.events.front() {
+            if at > frame {
+                break;
+            }
+            into.push((key, down));
+            self.events.pop_front();
+        }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_back_what_was_recorded() {
+        let path = std::env::temp_dir().join(format!("chip8-input-{}.tas", std::process::id()));
+        let session = Session {
+            rng: Generator::Xorshift,
+            seed: Some(1234),
+            instructions_per_frame: 10,
+        };
+        let mut recorder = InputRecorder::create(&path, session).unwrap();
+        recorder.record(3, &[(0x5, true), (0xA, true)]).unwrap();
+        recorder.record(7, &[(0x5, false)]).unwrap();
+        recorder.finish().unwrap();
+
+        let (loaded, mut playback) = InputPlayback::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, session);
+
+        let mut events = Vec::new();
+        playback.take(2, &mut events);
+        assert!(events.is_empty());
+        playback.take(3, &mut events);
+        assert_eq!(events, [(0x5, true), (0xA, true)]);
+        playback.take(10, &mut events);
+        assert_eq!(events[2..], [(0x5, false)]);
+        assert!(playback.is_finished());
+    }
+
+    #[test]
+    fn rejects_malformed_recordings() {
+        assert!(InputPlayback::parse("").is_err());
+        assert!(InputPlayback::parse("chip8-input seed=default").is_err());
+        assert!(InputPlayback::parse("chip8-input seed=default ipf=10\n5 +G").is_err());
+
+        let (session, _) = InputPlayback::parse("chip8-input seed=default ipf=10\n").unwrap();
+        assert_eq!(session.seed, None);
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use chip8::{Chip8, Chip8Error};
+
+/// Formats an instruction the way `--disassemble` lists it.
+pub(crate) fn listing_line(address: usize, opcode: u16, mnemonic: &str) -> String {
+    format!("{:03X}: {:04X}  {}", address, opcode, mnemonic)
+}
+
+/// Writes every executed instruction to a file, followed by the registers it changed.
+pub(crate) struct Tracer {
+    out: BufWriter<File>,
+}
+
+impl Tracer {
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Runs one instruction, tracing it if `tracer` is set. A failed write stops the trace rather
+    /// than the emulator, leaving `tracer` as `None`.
+    pub(crate) fn cycle(tracer: &mut Option<Self>, chip8: &mut Chip8) -> Result<u16, Chip8Error> {
+        let Some(t) = tracer else {
+            return chip8.cycle();
+        };
+
+        let before = Before::capture(chip8);
+        let result = chip8.cycle();
+        let line = before.line(chip8);
+        let written = match &result {
+            Ok(_) => writeln!(t.out, "{}", line),
+            Err(e) => writeln!(t.out, "{}  ; {}", line, e),
+        };
+        if let Err(e) = written {
+            eprintln!("Tracing stopped: {}", e);
+            *tracer = None;
+        }
+
+        result
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// What's needed from the machine before an instruction runs to describe it afterwards.
+struct Before {
+    listing: String,
+    registers: [u8; 16],
+    index: usize,
+}
+
+impl Before {
+    fn capture(chip8: &Chip8) -> Self {
+        let pc = chip8.pc();
+        // Disassembling a few bytes at PC folds in a double-width operand like the listing does
+        let (_, opcode, mnemonic) = chip8::disassemble(chip8.memory_slice(pc, 4))
+            .into_iter()
+            .next()
+            .unwrap_or((pc, 0, "DB".to_string()));
+
+        Self {
+            listing: listing_line(pc, opcode, &mnemonic),
+            registers: *chip8.registers(),
+            index: chip8.index(),
+        }
+    }
+
+    /// The instruction as listed, followed by the registers that now hold different values.
+    fn line(self, after: &Chip8) -> String {
+        let mut changes = Vec::new();
+        for (i, (old, new)) in self.registers.iter().zip(after.registers()).enumerate() {
+            if old != new {
+                changes.push(format!("V{:X}={:02x}", i, new));
+            }
+        }
+        if self.index != after.index() {
+            changes.push(format!("I={:#06x}", after.index()));
+        }
+
+        if changes.is_empty() {
+            self.listing
+        } else {
+            format!("{:<32}{}", self.listing, changes.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_changed_registers() {
+        // LD V3, 0x2A; LD I, 0x300; CLS
+        let mut chip8 = Chip8::from_bytes(&[0x63, 0x2A, 0xA3, 0x00, 0x00, 0xE0]).unwrap();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let before = Before::capture(&chip8);
+            chip8.cycle().unwrap();
+            lines.push(before.line(&chip8));
+        }
+
+        assert_eq!(
+            lines,
+            [
+                "200: 632A  LD V3, 0x2A          V3=2a",
+                "202: A300  LD I, 0x300          I=0x0300",
+                "204: 00E0  CLS",
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use chip8::{Chip8, Chip8Error};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::trace::Tracer;
+
+/// Most terminals only report key presses, so without release events a key counts as held for
+/// this many frames after it was last typed. Key repeat keeps it held for longer.
+const HOLD_FRAMES: u32 = 8;
+
+/// Runs the emulator in the terminal, drawing two pixels per character cell with half blocks,
+/// until Esc or Ctrl+C is pressed or an instruction fails. Returns the emulator so its state can
+/// be saved.
+pub(crate) fn run_tui(
+    mut chip8: Chip8,
+    instructions_per_frame: u32,
+    keymap: HashMap<char, usize>,
+    mute: bool,
+    tracer: &mut Option<Tracer>,
+) -> io::Result<Chip8> {
+    let mut stdout = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+    let result = enter(&mut stdout, release_events).and_then(|()| {
+        run_loop(
+            &mut chip8,
+            instructions_per_frame,
+            &keymap,
+            release_events,
+            mute,
+            tracer,
+        )
+    });
+    // Restored even if setting up or running failed, so the shell isn't left in raw mode
+    let restored = restore(&mut stdout, release_events);
+    let stopped = result?;
+    restored?;
+
+    // Only report the error once the terminal is usable again
+    if let Some(e) = stopped {
+        eprintln!("Emulation stopped at PC={:#06x}: {}", chip8.pc(), e);
+    }
+
+    Ok(chip8)
+}
+
+fn enter(stdout: &mut io::Stdout, release_events: bool) -> io::Result<()> {
+    if release_events {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)
+}
+
+/// Undoes [`enter`] and raw mode, carrying on past failures so as much as possible is restored.
+fn restore(stdout: &mut io::Stdout, release_events: bool) -> io::Result<()> {
+    let flags = if release_events {
+        execute!(stdout, PopKeyboardEnhancementFlags)
+    } else {
+        Ok(())
+    };
+    let screen = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+    let raw_mode = terminal::disable_raw_mode();
+
+    flags.and(screen).and(raw_mode)
+}
+
+fn run_loop(
+    chip8: &mut Chip8,
+    instructions_per_frame: u32,
+    keymap: &HashMap<char, usize>,
+    release_events: bool,
+    mute: bool,
+    tracer: &mut Option<Tracer>,
+) -> io::Result<Option<Chip8Error>> {
+    let mut stdout = io::stdout();
+    let frame_duration = Duration::from_secs(1) / Chip8::TIMER_FREQUENCY;
+    let mut next_frame = Instant::now();
+    // Frames left until each key is lifted, `None` while waiting for a release event
+    let mut held: [Option<u32>; 16] = [Some(0); 16];
+    let mut was_beeping = false;
+
+    chip8.force_redraw();
+
+    loop {
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            else {
+                continue;
+            };
+
+            match code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+                KeyCode::Char(c) => {
+                    if let Some(&idx) = keymap.get(&c.to_ascii_lowercase()) {
+                        if kind == KeyEventKind::Release {
+                            chip8.lift_key(idx);
+                            held[idx] = Some(0);
+                        } else {
+                            chip8.press_key(idx);
+                            held[idx] = (!release_events).then_some(HOLD_FRAMES);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for _ in 0..instructions_per_frame {
+            if let Err(e) = Tracer::cycle(tracer, chip8) {
+                return Ok(Some(e));
+            }
+            if chip8.is_waiting_for_vblank() {
+                break;
+            }
+        }
+        chip8.tick_timers();
+
+        for (idx, frames) in held.iter_mut().enumerate() {
+            if let Some(n @ 1..) = frames {
+                *n -= 1;
+                if *n == 0 {
+                    chip8.lift_key(idx);
+                }
+            }
+        }
+
+        // Ring the terminal bell in place of the buzzer
+        let beeping = chip8.is_beeping();
+        if beeping && !was_beeping && !mute {
+            queue!(stdout, crossterm::style::Print('\x07'))?;
+        }
+        was_beeping = beeping;
+
+        if chip8.is_dirty() {
+            let (width, _) = chip8.resolution();
+            queue!(stdout, cursor::MoveTo(0, 0))?;
+            for line in half_blocks(chip8.get_video(), width) {
+                queue!(
+                    stdout,
+                    crossterm::style::Print(line),
+                    cursor::MoveToNextLine(1)
+                )?;
+            }
+            chip8.set_clean();
+        }
+        stdout.flush()?;
+
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame < now {
+            // Don't try to catch up after falling behind, e.g. while the terminal was suspended
+            next_frame = now;
+        }
+    }
+}
+
+/// Packs each pair of pixel rows into one line of text, using upper and lower half blocks.
+fn half_blocks(video: &[u32], width: usize) -> Vec<String> {
+    video
+        .chunks(width * 2)
+        .map(|rows| {
+            let (top, bottom) = rows.split_at(width.min(rows.len()));
+            (0..width)
+                .map(|x| {
+                    let upper = top.get(x).is_some_and(|&p| p != 0);
+                    let lower = bottom.get(x).is_some_and(|&p| p != 0);
+                    match (upper, lower) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_two_rows_per_line() {
+        #[rustfmt::skip]
+        let video = [
+            1, 0, 1, 0,
+            1, 1, 0, 0,
+            0, 1, 0, 0,
+        ];
+
+        assert_eq!(half_blocks(&video, 4), ["█▄▀ ", " ▀  "]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use chip8::Chip8Error;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the ROM has to stop changing before it's reloaded, so an editor or assembler writing
+/// it in several steps only triggers one reload.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches a ROM file for changes, for reloading it while it's being developed.
+pub(crate) struct RomWatcher {
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    changes: Receiver<()>,
+    path: PathBuf,
+    /// When the file last changed, if it hasn't been reloaded since
+    pending: Option<Instant>,
+}
+
+impl RomWatcher {
+    pub(crate) fn new(path: &Path) -> notify::Result<Self> {
+        let (tx, changes) = mpsc::channel();
+        let name: Option<OsString> = path.file_name().map(Into::into);
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let touches_rom = event.paths.iter().any(|p| p.file_name() == name.as_deref());
+                if touches_rom && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                {
+                    let _ = tx.send(());
+                }
+            })?;
+
+        // Editors often save by replacing the file, so watch the directory it's in
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+            path: path.to_path_buf(),
+            pending: None,
+        })
+    }
+
+    /// Returns the ROM's new contents once the file has changed and then been left alone for a
+    /// moment.
+    pub(crate) fn poll(&mut self) -> Option<Result<Vec<u8>, Chip8Error>> {
+        if self.changes.try_iter().count() > 0 {
+            self.pending = Some(Instant::now());
+        }
+
+        match self.pending {
+            Some(changed) if changed.elapsed() >= DEBOUNCE => {
+                self.pending = None;
+                let rom = fs::read(&self.path).map_err(Chip8Error::from);
+                #[cfg(feature = "gzip")]
+                let rom = rom.and_then(chip8::decompress_rom);
+                Some(rom)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_rom_after_it_changes() {
+        let dir = std::env::temp_dir().join(format!("chip8-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.ch8");
+        fs::write(&path, [0x00, 0xE0]).unwrap();
+
+        let mut watcher = RomWatcher::new(&path).unwrap();
+        assert!(watcher.poll().is_none());
+        fs::write(&path, [0x12, 0x00]).unwrap();
+
+        let started = Instant::now();
+        let rom = loop {
+            if let Some(rom) = watcher.poll() {
+                break rom.unwrap();
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "no change seen");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rom, [0x12, 0x00]);
+    }
+}
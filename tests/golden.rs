@@ -0,0 +1,122 @@
+//! Runs every ROM under `tests/roms/` under each quirk set and compares a hash of the final frame
+//! with the one recorded in `tests/goldens.txt`. `.asm` sources are assembled first; `.ch8` files
+//! are loaded as they are, so downloaded test ROMs can be dropped in next to them.
+//!
+//! After an intended change to the output, rerun with `UPDATE_GOLDENS=1` to rewrite the file.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8::{assemble, Chip8, Quirks};
+
+const FRAMES: u32 = 60;
+const INSTRUCTIONS_PER_FRAME: u32 = 20;
+
+fn quirk_sets() -> [(&'static str, Quirks); 3] {
+    [
+        ("schip", Quirks::default()),
+        (
+            "vip",
+            Quirks {
+                shift_uses_vy: true,
+                load_store_increments_index: true,
+                display_wraps: false,
+                vf_reset_on_logic: true,
+                display_wait: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            "jump",
+            Quirks {
+                jump_with_offset_uses_vx: true,
+                ..Quirks::default()
+            },
+        ),
+    ]
+}
+
+fn load(path: &Path) -> Vec<u8> {
+    let contents = fs::read(path).unwrap();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("asm") => {
+            let source = String::from_utf8(contents).unwrap();
+            assemble(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+        }
+        _ => contents,
+    }
+}
+
+fn run(rom: &[u8], quirks: Quirks) -> Chip8 {
+    let mut chip8 = Chip8::from_bytes(rom).unwrap().with_quirks(quirks);
+    for _ in 0..FRAMES {
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            chip8.cycle().unwrap();
+            if chip8.is_waiting_for_vblank() {
+                break;
+            }
+        }
+        chip8.tick_timers();
+    }
+    chip8
+}
+
+#[test]
+fn final_frames_match_the_goldens() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let goldens_path = dir.join("goldens.txt");
+    let goldens: BTreeMap<String, String> = fs::read_to_string(&goldens_path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .map(|(name, hash)| (name.to_string(), hash.to_string()))
+        .collect();
+
+    let mut roms: Vec<_> = fs::read_dir(dir.join("roms"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("asm" | "ch8")
+            )
+        })
+        .collect();
+    roms.sort();
+
+    let mut actual = BTreeMap::new();
+    let mut mismatches = Vec::new();
+    for path in &roms {
+        let rom = load(path);
+        let name = path.file_name().unwrap().to_string_lossy();
+        for (set, quirks) in quirk_sets() {
+            let chip8 = run(&rom, quirks);
+            let key = format!("{} {}", name, set);
+            let hash = format!("{:016x}", chip8.video_hash());
+            if goldens.get(&key) != Some(&hash) {
+                mismatches.push(format!("{}:\n{}", key, chip8.render_ascii()));
+            }
+            actual.insert(key, hash);
+        }
+    }
+
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        let contents: String = actual
+            .iter()
+            .map(|(key, hash)| format!("{} {}\n", key, hash))
+            .collect();
+        fs::write(&goldens_path, contents).unwrap();
+        return;
+    }
+
+    assert!(
+        mismatches.is_empty() && goldens.len() == actual.len(),
+        "{} of {} frames differ from tests/goldens.txt, rerun with UPDATE_GOLDENS=1 if the \
+         change is intended\n\n{}",
+        mismatches.len(),
+        actual.len(),
+        mismatches.join("\n")
+    );
+}
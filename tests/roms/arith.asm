@@ -0,0 +1,111 @@
+; Shows the results of arithmetic, flags and quirk-dependent instructions as
+; 3-digit decimal numbers, four to a row:
+;   8xy4 sum, carry, 8xy5 difference, not borrow
+;   8xy7 difference, not borrow, 8xy6 result, shifted out bit
+;   8xyE result, shifted out bit, 8xy1 result, VF afterwards
+;   Fx65 after Fx55, Bnnn target
+; and finally an 8 drawn across the right edge
+        LD V8, 0        ; x
+        LD V9, 0        ; y
+
+        LD V3, 200
+        LD V4, 100
+        ADD V3, V4
+        LD VB, VF
+        LD VA, V3
+        CALL show
+        LD VA, VB
+        CALL show
+        LD V3, 100
+        LD V4, 200
+        SUB V3, V4
+        LD VB, VF
+        LD VA, V3
+        CALL show
+        LD VA, VB
+        CALL show
+        CALL row
+
+        LD V3, 30
+        LD V4, 100
+        SUBN V3, V4
+        LD VB, VF
+        LD VA, V3
+        CALL show
+        LD VA, VB
+        CALL show
+        LD V5, 0x81
+        LD V6, 0x06
+        SHR V5, V6      ; shifts V6 into V5 under the shift quirk
+        LD VB, VF
+        LD VA, V5
+        CALL show
+        LD VA, VB
+        CALL show
+        CALL row
+
+        LD V5, 0x81
+        LD V6, 0x43
+        SHL V5, V6
+        LD VB, VF
+        LD VA, V5
+        CALL show
+        LD VA, VB
+        CALL show
+        LD VF, 7
+        LD V3, 0x0C
+        LD V4, 0x0A
+        OR V3, V4       ; clears VF under the VF reset quirk
+        LD VB, VF
+        LD VA, V3
+        CALL show
+        LD VA, VB
+        CALL show
+        CALL row
+
+        LD V0, 42
+        LD V1, 43
+        LD V2, 44
+        LD I, values
+        LD [I], V2      ; moves I past the registers under the load/store quirk
+        LD V2, 9
+        LD V0, [I]
+        LD VA, V0
+        CALL show
+        LD V0, 0
+        LD V2, 4
+        JP V0, table    ; jumps to table + V2 under the jump quirk
+table:  LD VA, 1
+        JP shown
+        LD VA, 2
+        JP shown
+shown:  CALL show
+        CALL row
+        LD V3, 62
+        LD V4, 8
+        LD F, V4
+        DRW V3, V9, 5   ; wraps to the left edge unless sprites are clipped
+end:    JP end
+
+; Draws VA as three decimal digits at (V8, V9) and moves V8 along
+show:   LD I, digits
+        LD B, VA
+        LD V2, [I]
+        LD F, V0
+        DRW V8, V9, 5
+        ADD V8, 5
+        LD F, V1
+        DRW V8, V9, 5
+        ADD V8, 5
+        LD F, V2
+        DRW V8, V9, 5
+        ADD V8, 6
+        RET
+
+; Starts a new row of numbers
+row:    LD V8, 0
+        ADD V9, 6
+        RET
+
+values: DB 0x11, 0x22, 0x33, 0x44, 0x55, 0x66
+digits: DB 0x00, 0x00, 0x00
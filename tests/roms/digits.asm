@@ -0,0 +1,15 @@
+; Draws the 16 hex digits of the built-in font in two rows of eight
+        LD V0, 0        ; digit
+        LD V1, 2        ; x
+        LD V2, 4        ; y
+loop:   LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 8
+        SE V0, 8
+        JP next
+        LD V1, 2        ; second row
+        LD V2, 16
+next:   SE V0, 16
+        JP loop
+end:    JP end